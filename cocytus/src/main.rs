@@ -12,7 +12,7 @@
 
 use std::process::Command;
 use mythos_core::{cli::clean_cli_args, logger::set_id, printerror, printinfo};
-use pt_core::{get_user_selection, validate_pkgs, Query, QueryResult};
+use pt_core::{backend::XbpsBackend, get_user_selection, pkgdb::PkgDb, tertiary::ExternalManager, validate_pkgs, Query, QueryResult, SearchOpts};
 
 fn main() {
     let _ = set_id("COCYTUS");
@@ -20,15 +20,23 @@ fn main() {
     // This is passed to styx or lethe, if the user chooses to do so.
     let mut do_dry_run = false;
     let mut pkgs: Vec<String> = Vec::new();
+    let mut backend = XbpsBackend::new(ExternalManager::load());
+    let mut search_opts = SearchOpts::load();
 
     for arg in args {
         if arg == "-h" || arg == "--help" {
-        println!("Wrapper for xbps-query -Rs (xrs). Allows the user to select from the results and pipe them to either styx or lethe.\ncocytus -h|--help\t\tPrint this menu\ncocytus [pkgs]\t\tQuery [pkgs].");
+        println!("Wrapper for xbps-query -Rs (xrs). Allows the user to select from the results and pipe them to either styx or lethe.\ncocytus -h|--help\t\tPrint this menu\ncocytus [pkgs]\t\tQuery [pkgs].\ncocytus -d|--descriptions [pkgs]\t\tAlso search package descriptions.\ncocytus -e|--exact [pkgs]\t\tOnly accept exact package names. Exit with an error if any are missing.\ncocytus --glob|--regex [pkgs]\t\tTreat pkgs as glob or regex patterns. Detected by syntax otherwise (font-*, ^python3-.*-devel$).\ncocytus --scorer=<fuzzy|prefix|substring|levenshtein|regex> [pkgs]\t\tStrategy used to match packages.\ncocytus --threshold=<0-1> [pkgs]\t\tMinimum score a package needs to match.\ncocytus --refresh-index\t\tRebuild the package search index.");
             return;
         } 
         if arg == "-n" || arg == "--dryrun" {
             do_dry_run = true;
         }
+        else if arg == "--refresh-index" {
+            backend.refresh_index = true;
+        }
+        else if search_opts.parse_arg(&arg) {
+            continue;
+        }
         else if !arg.starts_with("-") {
             pkgs.push(arg);
         } else {
@@ -43,12 +51,18 @@ fn main() {
     }
 
 
-    let mut validated_pkgs = Query::from(match validate_pkgs(pkgs.into_iter()) {
-        Some(pkgs) => pkgs,
-        None => {
+    let mut validated_pkgs = Query::from(match validate_pkgs(&backend, pkgs.into_iter(), &search_opts) {
+        Ok(Some(pkgs)) => pkgs,
+        Ok(None) => {
             printinfo!("Exiting...");
             return;
-        }
+        },
+        Err(errors) => {
+            for msg in errors {
+                printerror!("{msg}");
+            }
+            return;
+        },
     });
 
     if validated_pkgs.len() == 0 {
@@ -70,7 +84,7 @@ fn main() {
                 return;
             },
             3 => {
-                validated_pkgs = match print_pkg_info(validated_pkgs) {
+                validated_pkgs = match print_pkg_info(&backend, validated_pkgs) {
                     Some(pkgs) => pkgs,
                     None => return
                 }
@@ -80,17 +94,28 @@ fn main() {
     }
 }
 
-fn print_pkg_info(query: Query) -> Option<Query> {
+fn print_pkg_info(backend: &XbpsBackend, query: Query) -> Option<Query> {
+    //! Page through the details of each package. Returns the query, once the user is done.
     let msg = "\n0. Return\n1. Previous\n2. Next\nOption: ";
-    let mut pkgs: Vec<QueryResult> = Vec::new();
+    let pkgs: Vec<QueryResult> = query.into_iter().collect();
+    let pkgdb = PkgDb::load().ok();
+    let mut index = 0;
 
-    for pkg in query {
-        let info = pkg.display();
-        println!("\nShowing info for \"{}\"", pkg.pkg_name);
-        println!("{info}");
-    }
+    loop {
+        let pkg = &pkgs[index];
+        println!("\nShowing info for \"{}\" ({}/{})", pkg.pkg_name, index + 1, pkgs.len());
+        println!("{}", pkg.long_display(backend));
+        if let Some(reason) = pkgdb.as_ref().and_then(|db| db.install_reason(&pkg.pkg_name)) {
+            println!("{reason}");
+        }
 
-    return None;
+        match get_user_selection(msg, 2) {
+            0 => return Some(Query::from(pkgs)),
+            1 => index = index.checked_sub(1).unwrap_or(pkgs.len() - 1),
+            2 => index = (index + 1) % pkgs.len(),
+            _ => panic!("User input should have been evaluated earlier")
+        };
+    }
 }
 fn pipe_to_styx(pkgs: Query, do_dryrun: bool) {
     // Check if user has sudo privileges   
@@ -103,7 +128,7 @@ fn pipe_to_styx(pkgs: Query, do_dryrun: bool) {
     if do_dryrun {
         cmd.arg("-n");
     } 
-    cmd.args(pkgs.get_pkg_names());
+    cmd.args(pkgs.get_pkg_specs());
     let _ = match cmd.spawn() {
         Ok(mut child) => child.wait(),
         Err(msg) => {
@@ -120,7 +145,7 @@ fn pipe_to_lethe(pkgs:Query, do_dryrun: bool) {
     if do_dryrun {
         cmd.arg("-n");
     } 
-    cmd.args(pkgs.get_pkg_names());
+    cmd.args(pkgs.get_pkg_specs());
     let _ = match cmd.spawn() {
         Ok(mut child) => child.wait(),
         Err(msg) => {
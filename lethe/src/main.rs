@@ -2,14 +2,17 @@
  * Simple wrapper for xbps-remove command -Ro.
  */
 
-use duct::cmd;
-use mythos_core::{cli::{clean_cli_args, get_user_permission}, printerror, printfatal, printinfo, logger::set_id};
-use pt_core::{validate_pkgs, Query, QueryResult};
-fn main() {
+use std::process::ExitCode;
+
+use mythos_core::{cli::{clean_cli_args, get_user_permission}, printerror, printfatal, printinfo, printwarn, logger::set_id};
+use pt_core::{backend::{PackageBackend, XbpsBackend}, charon, pkgdb::PkgDb, tertiary::{list_installed_flatpaks, run_tertiary, ExternalManager, TertiaryAction, TertiaryPkg}, validate_pkgs_tertiary, xbps_events::XbpsEvent, PkgOrigin, Query, QueryResult, SearchOpts};
+fn main() -> ExitCode {
     let _ = set_id("LETHE");
     let args = clean_cli_args();
     let mut pkgs: Vec<String> = Vec::new();
     let mut do_dry_run = false;
+    let mut backend = XbpsBackend::new(ExternalManager::load());
+    let mut search_opts = SearchOpts::load();
 
     // Parse opts.
     for arg in args {
@@ -17,61 +20,177 @@ fn main() {
             println!("Wrapper util for xbps-remove -Ryo");
             println!("lethe [opts] pkgs");
             println!("opts:");
-            println!("-h | --help\t\tPrint this menu.\n-n | --dryrun\t\tRun command w/o making changes to system.");
-            return;
+            println!("-h | --help\t\tPrint this menu.\n-n | --dryrun\t\tRun command w/o making changes to system.\n-d | --descriptions\t\tAlso search package descriptions.\n-e | --exact\t\tOnly accept exact package names. Exit with an error if any are missing.\n--glob | --regex\t\tTreat pkgs as glob or regex patterns. Detected by syntax otherwise (font-*, ^python3-.*-devel$).\n--scorer=<fuzzy|prefix|substring|levenshtein|regex>\t\tStrategy used to match packages.\n--threshold=<0-1>\t\tMinimum score a package needs to match.\n--refresh-index\t\tRebuild the package search index.\nPackages can be pinned to versions with xbps patterns: foo>=1.2, foo<2.0, foo-1.2_1");
+            return ExitCode::SUCCESS;
         } 
         if arg == "-n" || arg == "--dryrun" {
             do_dry_run = true;
         }
+        else if arg == "--refresh-index" {
+            backend.refresh_index = true;
+        }
+        else if search_opts.parse_arg(&arg) {
+            continue;
+        }
         else if !arg.starts_with("-") {
             pkgs.push(arg);
         } else {
             printerror!("Unknown opt: '{arg}'");
-            return;
+            return ExitCode::FAILURE;
         }
     }
 
     // Validate packages
     // Ensure package(s) actually exist.
-    let mut removed_pkgs = false;
-    let validated_pkgs = Query::from(match validate_pkgs(pkgs.into_iter()) {
-        // Only grab packages that are installed.
-        Some(pkgs) => pkgs.into_iter().filter(|p| { removed_pkgs = true; p.is_installed}).collect::<Vec<QueryResult>>(),
-        None => {
+    let pkgdb = PkgDb::load().ok();
+    let (validated_pkgs, tertiary) = match validate_pkgs_tertiary(&backend, pkgs.into_iter(), &search_opts) {
+        Ok(Some(res)) => res,
+        Ok(None) => {
             printinfo!("Exiting");
-            return;
+            return ExitCode::SUCCESS;
+        },
+        Err(errors) => {
+            for msg in errors {
+                printerror!("{msg}");
+            }
+            return ExitCode::FAILURE;
+        },
+    };
+
+    // Only grab packages that are installed. Every failure is reported before exiting.
+    let mut errors: Vec<String> = Vec::new();
+    let mut installed_pkgs: Vec<QueryResult> = Vec::new();
+    for p in validated_pkgs {
+        let is_installed = match &pkgdb {
+            Some(pkgdb) if p.origin.is_xbps() => pkgdb.is_installed(&p.pkg_name),
+            _ => p.is_installed,
+        };
+        if !is_installed && search_opts.exact {
+            errors.push(format!("'{}' is not currently installed", p.pkg_name));
+            continue;
+        }
+        if !is_installed {
+            printinfo!("Removed '{}', it is not currently installed", p.pkg_name);
+            continue;
+        }
+        // The installed version has to satisfy the constraint, not just any available one.
+        let installed = pkgdb.as_ref().and_then(|db| db.get(&p.pkg_name));
+        if let (Some(spec), Some(installed)) = (&p.pkg_spec, installed) {
+            if !spec.matches(&installed.pkg_version) {
+                errors.push(format!("Installed version of '{}' ({}) does not satisfy '{spec}'", p.pkg_name, installed.pkg_version));
+                continue;
+            }
+        }
+        installed_pkgs.push(p);
+    }
+    // Appstream matches include apps that were never installed. flatpak is only asked if one of them is a flatpak.
+    let mut installed_flatpaks: Vec<String> = Vec::new();
+    if tertiary.iter().any(|p| p.manager == "flatpak") {
+        installed_flatpaks = list_installed_flatpaks();
+    }
+    let mut installed_tertiary: Vec<TertiaryPkg> = Vec::new();
+    for p in tertiary {
+        if !p.is_installed(&installed_flatpaks) {
+            printinfo!("Removed '{}', it is not currently installed", p.pkg_id);
+            continue;
         }
-    });
+        installed_tertiary.push(p);
+    }
+    let tertiary = installed_tertiary;
 
-    // If all packages were removed, exit
-    if removed_pkgs {
-        println!("Removed packages not currently installed");
+    if errors.len() > 0 {
+        for msg in errors {
+            printerror!("{msg}");
+        }
+        return ExitCode::FAILURE;
     }
+    let validated_pkgs = Query::from(installed_pkgs);
+
+    // Only once nothing else can fail validation, so a failure doesn't leave a request half done.
+    // lethe never assumes yes, so each tertiary package is confirmed on its own.
+    run_tertiary(&tertiary, TertiaryAction::Remove, do_dry_run, false);
+
+    // If all packages were removed, exit
     if validated_pkgs.len() == 0 {
-        printinfo!("All packages were removed. Exiting...");
-        return;
+        if tertiary.len() == 0 {
+            printinfo!("All packages were removed. Exiting...");
+        }
+        return ExitCode::SUCCESS;
     }
 
-    // Give user option to exit.
-    let msg = format!("The following packages will be removed.{}", validated_pkgs.get_short_list());
-    if get_user_permission(false, &msg) {
-        printinfo!("Exiting");
-        return;
+    // Warn about packages other packages still depend on.
+    if let Some(pkgdb) = &pkgdb {
+        for name in validated_pkgs.clone().into_iter().filter(|p| p.origin.is_xbps()).map(|p| p.pkg_name) {
+            let required_by = pkgdb.required_by(&name).iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>();
+            if required_by.len() > 0 {
+                printwarn!("'{name}' is required by: {}", required_by.join(", "));
+            }
+        }
     }
 
-    // Create args list
-    let pkg_names = validated_pkgs.get_pkg_names();
-    let mut args = vec!["-Ryo"];
-    if do_dry_run {
-        args.push("-n".into());
+    // Charon programs aren't in the pkgdb, xbps-remove would refuse the whole transaction.
+    let (charon_pkgs, xbps_pkgs): (Vec<QueryResult>, Vec<QueryResult>) = validated_pkgs.into_iter().partition(|p| p.origin == PkgOrigin::Charon);
+    let pkg_names = xbps_pkgs.iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>();
+
+    // Give user option to exit. The dry run also shows the orphans that go along with them.
+    let mut msg = "The following packages will be removed:\n".to_owned();
+    if xbps_pkgs.len() > 0 {
+        let plan = match backend.plan_remove(&pkg_names) {
+            Ok(plan) => plan,
+            Err(msg) => {
+                printerror!("Could not preview transaction: {msg}");
+                return ExitCode::FAILURE;
+            },
+        };
+        plan.report();
+        msg += &format!("{}\n", plan.table());
+    }
+    if charon_pkgs.len() > 0 {
+        msg += &format!("\nUninstalled by charon:\n{}\n", charon_pkgs.iter().map(|p| p.list_name()).collect::<Vec<String>>().join("\n"));
+    }
+    if !get_user_permission(false, &msg) {
+        printinfo!("Exiting");
+        return ExitCode::SUCCESS;
     }
-    args.extend(pkg_names);
 
     // Remove packages.
-    match cmd("xbps-remove", args).run() {
-        Ok(_) => printinfo!("Success! Exiting"),
-        Err(msg) => printfatal!("{msg}"),
+    if xbps_pkgs.len() > 0 {
+        if let Err(msg) = remove_pkgs(&backend, &pkg_names, do_dry_run) {
+            printerror!("{msg}");
+            return ExitCode::FAILURE;
+        }
+    }
+    for pkg in charon_pkgs {
+        if let Err(msg) = charon::uninstall(&pkg.pkg_name, do_dry_run) {
+            printfatal!("{msg}");
+        }
+    }
+    printinfo!("Success! Exiting");
+    return ExitCode::SUCCESS;
+}
+fn remove_pkgs(backend: &dyn PackageBackend, pkg_names: &[&str], do_dry_run: bool) -> Result<(), std::io::Error> {
+    let mut removed: Vec<String> = Vec::new();
+    let mut failure: Option<String> = None;
+    let transaction = backend.remove(pkg_names, do_dry_run)?;
+    let command = transaction.command.to_owned();
+    for event in transaction {
+        match event {
+            XbpsEvent::Removed(pkgver) => removed.push(pkgver),
+            XbpsEvent::Warning(msg) => printwarn!("{msg}"),
+            XbpsEvent::Error(err) => printerror!("{err}"),
+            XbpsEvent::Exited(Some(0)) => (),
+            XbpsEvent::Exited(_) => failure = Some(format!("'{command}' failed. {event}")),
+            _ => println!("{event}"),
+        };
+    }
+    // Orphans are removed too, so this can be more than what was asked for.
+    if removed.len() > 0 {
+        printinfo!("Removed {}", removed.join(", "));
     }
+    return match failure {
+        Some(msg) => Err(std::io::Error::other(msg)),
+        None => Ok(()),
+    };
 }
 
 #[cfg(test)]
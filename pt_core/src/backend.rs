@@ -0,0 +1,298 @@
+/*!
+ * Pluggable access to the system package manager.
+ *
+ * XbpsBackend talks to a live Void system.
+ * FakeBackend keeps its packages in memory, so styx, lethe and cocytus can be tested anywhere.
+ */
+use std::{borrow::Cow, cell::{OnceCell, RefCell}, collections::HashSet, io::{self, BufRead}, path::Path};
+
+use mythos_core::printwarn;
+
+use crate::{index::SearchIndex, ngram::IndexedListing, package_info::PackageInfo, pkgdb::PkgDb, plan::{PlanAction, PlanEntry, TransactionPlan}, repodata::{read_all_repodata, XBPS_DB_DIR}, tertiary::{query_managers, query_tertiary, ExternalManager, TertiaryPkg}, utils::{pattern_name, split_name_block}, xbps_cmd::{Transaction, XbpsCommand}, xbps_output::XbpsLines, QueryResult, SearchOpts};
+
+
+pub trait PackageBackend {
+    /// Every package the backend's repositories know about. Scores are left at 0.
+    fn list_available(&self) -> io::Result<Vec<QueryResult>>;
+    /// Every package currently installed on the system.
+    fn list_installed(&self) -> io::Result<Vec<QueryResult>>;
+    /// Details about a single package. Installed packages are preferred over the repositories.
+    fn info(&self, pkg_name: &str) -> io::Result<PackageInfo>;
+    /// Install pkgs. Returns the events of the running transaction.
+    fn install(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction>;
+    /// Remove pkgs and their orphans. Returns the events of the running transaction.
+    fn remove(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction>;
+    /// Update pkgs. If pkgs is empty, update the entire system.
+    fn update(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction>;
+    /// Packages each search term names in a tertiary package manager (flatpak, [[external]]), in the same order as search_terms.
+    fn search_tertiary(&self, search_terms: &[&str]) -> Vec<Vec<TertiaryPkg>>;
+
+    /// What install() would do, including dependencies and forced updates. Nothing is changed, not even the repository index.
+    fn plan_install(&self, pkgs: &[&str]) -> io::Result<TransactionPlan>;
+    /// What remove() would do, including orphans. Nothing is changed.
+    fn plan_remove(&self, pkgs: &[&str]) -> io::Result<TransactionPlan>;
+
+    fn shlib_updates(&self, broken: &[(String, String)]) -> io::Result<Vec<String>> {
+        /*!
+         * Installed packages that need updating to fix broken shlibs, given as (pkgver, shlib).
+         * - The installed package that provides an older version of shlib, e.g. python3 for libpython3.12.so.1.0.
+         * - pkgver itself, if it is installed and needs a rebuild against a newer shlib.
+         *
+         * Packages without a newer version available are skipped, since updating them won't help.
+         */
+        let available = self.list_available()?;
+        let installed = self.list_installed()?;
+        let has_update = |pkg: &QueryResult| available.iter().any(|p| p.pkg_name == pkg.pkg_name && p.pkg_version > pkg.pkg_version);
+
+        let mut output: Vec<String> = Vec::new();
+        for (pkgver, shlib) in broken {
+            let (pkg_name, pkg_version) = split_name_block(pkgver);
+            let providers = available.iter().filter(|p| p.pkg_shlib_provides.contains(shlib)).map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>();
+            let targets = installed.iter()
+                .filter(|p| providers.contains(&p.pkg_name.as_str()) || (p.pkg_name == pkg_name && p.pkg_version == pkg_version))
+                .filter(|p| has_update(p));
+            for pkg in targets {
+                if !output.contains(&pkg.pkg_name) {
+                    output.push(pkg.pkg_name.to_owned());
+                }
+            }
+        }
+        return Ok(output);
+    }
+    fn indexed_listing(&self) -> io::Result<Cow<'_, IndexedListing>> {
+        //! list_available(), indexed for searching. Built on every call, unless the backend keeps it.
+        return Ok(Cow::Owned(IndexedListing::new(self.list_available()?)));
+    }
+    fn search(&self, search_term: &str, opts: &SearchOpts) -> io::Result<Vec<QueryResult>> {
+        //! Fuzzy find search_term in list_available(), along with the packages providing it. Only results scoring above opts.threshold are kept.
+        return Ok(self.search_many(&[search_term], opts)?.remove(0));
+    }
+    fn search_many(&self, search_terms: &[&str], opts: &SearchOpts) -> io::Result<Vec<Vec<QueryResult>>> {
+        /*!
+         * Search for each term, but the listing is only indexed once. Results are in the same order as search_terms.
+         * Packages providing a search term (e.g. gawk for awk) are included, since virtual packages have no entry of their own.
+         */
+        let listing = self.indexed_listing()?;
+        return Ok(search_terms.iter().map(|term| {
+            let providers = listing.providers(term);
+            let mut results = listing.search(term, opts);
+            results.retain(|p| !providers.contains(p));
+            results.extend(providers);
+            results
+        }).collect());
+    }
+}
+
+/**
+ * Backend for a live Void system.
+ * Listings are read from the cached repodata, transactions go through xbps-install/xbps-remove.
+ *
+ * refresh_index: Rebuild the search index, even if it is current.
+ * externals: Searched for packages xbps doesn't have. Empty unless loaded, e.g. with ExternalManager::load().
+ * listing: Indexed the first time it is searched, then kept for the life of the backend.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct XbpsBackend {
+    pub refresh_index: bool,
+    pub externals: Vec<ExternalManager>,
+    listing: OnceCell<IndexedListing>,
+}
+
+impl XbpsBackend {
+    pub fn new(externals: Vec<ExternalManager>) -> XbpsBackend {
+        return XbpsBackend { externals, ..Default::default() };
+    }
+    fn query_all(&self) -> io::Result<Vec<QueryResult>> {
+        //! Fallback for when repodata can't be read: xbps-query -Rs "" lists every package.
+        // Using search_term here works, unless no pkgs are found.
+        // So if the user does cocytus 'bledner' instead of 'blender', it will return nothing.
+        // Listing everything allows the query to find what the user likely meant.
+        let reader = XbpsCommand::new("xbps-query").args(&["-Rs", ""]).reader()?;
+        return Ok(read_xbps_lines(XbpsLines::search_output(reader)));
+    }
+}
+
+fn read_xbps_lines<R: BufRead>(lines: XbpsLines<R>) -> Vec<QueryResult> {
+    //! Lines that can't be parsed are skipped with a warning.
+    return lines.filter_map(|res| match res {
+        Ok(pkg) => Some(pkg),
+        Err(err) => {
+            printwarn!("Could not parse xbps-query output: {err}");
+            None
+        }
+    }).collect();
+}
+
+impl PackageBackend for XbpsBackend {
+    fn list_available(&self) -> io::Result<Vec<QueryResult>> {
+        let db_dir = Path::new(XBPS_DB_DIR);
+        if !self.refresh_index {
+            if let Some(index) = SearchIndex::load().filter(|index| index.is_current(db_dir)) {
+                return Ok(index.packages);
+            }
+        }
+
+        let mut pkgs = match read_all_repodata(db_dir) {
+            Ok(pkgs) => pkgs,
+            Err(_) => return self.query_all(),
+        };
+
+        // Repodata doesn't know what is installed.
+        let installed = self.list_installed()?.into_iter().map(|p| p.pkg_name).collect::<HashSet<String>>();
+        for pkg in pkgs.iter_mut() {
+            pkg.is_installed = installed.contains(&pkg.pkg_name);
+        }
+
+        let index = SearchIndex::new(db_dir, pkgs);
+        if let Err(msg) = index.save() {
+            printwarn!("Could not save search index: {msg}");
+        }
+        return Ok(index.packages);
+    }
+    fn list_installed(&self) -> io::Result<Vec<QueryResult>> {
+        if let Ok(pkgdb) = PkgDb::load() {
+            return Ok(pkgdb.iter().map(QueryResult::from).collect());
+        }
+        // Fallback for when pkgdb can't be read.
+        let reader = XbpsCommand::new("xbps-query").arg("-l").reader()?;
+        return Ok(read_xbps_lines(XbpsLines::list_output(reader)));
+    }
+    fn info(&self, pkg_name: &str) -> io::Result<PackageInfo> {
+        // -S only knows about installed packages. Fall back to the repositories.
+        for flags in ["-S", "-RS"] {
+            let output = XbpsCommand::new("xbps-query").args(&[flags, pkg_name]).output()?;
+            if output.status.success() && !output.stdout.is_empty() {
+                return Ok(PackageInfo::parse(&output.stdout));
+            }
+        }
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("Package not found: '{pkg_name}'")));
+    }
+    fn install(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction> {
+        return XbpsCommand::new("xbps-install").arg("-Sy").dry_run(do_dry_run).args(pkgs).spawn();
+    }
+    fn remove(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction> {
+        return XbpsCommand::new("xbps-remove").arg("-Ryo").dry_run(do_dry_run).args(pkgs).spawn();
+    }
+    fn update(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction> {
+        return XbpsCommand::new("xbps-install").arg("-Syu").dry_run(do_dry_run).args(pkgs).spawn();
+    }
+    fn plan_install(&self, pkgs: &[&str]) -> io::Result<TransactionPlan> {
+        // Without -S, the cached repodata is used. A preview shouldn't need the network, or root.
+        let transaction = XbpsCommand::new("xbps-install").dry_run(true).args(pkgs).spawn()?;
+        return Ok(TransactionPlan::from_events(transaction, &self.list_installed()?));
+    }
+    fn plan_remove(&self, pkgs: &[&str]) -> io::Result<TransactionPlan> {
+        let transaction = XbpsCommand::new("xbps-remove").arg("-Ro").dry_run(true).args(pkgs).spawn()?;
+        return Ok(TransactionPlan::from_events(transaction, &self.list_installed()?));
+    }
+    fn indexed_listing(&self) -> io::Result<Cow<'_, IndexedListing>> {
+        if self.listing.get().is_none() {
+            let _ = self.listing.set(IndexedListing::new(self.list_available()?));
+        }
+        return Ok(Cow::Borrowed(self.listing.get().unwrap()));
+    }
+    fn search_tertiary(&self, search_terms: &[&str]) -> Vec<Vec<TertiaryPkg>> {
+        return query_tertiary(search_terms, &self.externals);
+    }
+}
+
+/**
+ * In-memory backend.
+ * Transactions update the installed flag of `packages` and are recorded in `calls()`.
+ * Every transaction writes `output` to stdout and `stderr` to stderr, then exits with `exit_code`, which lets tests script package manager messages.
+ * Plans are built from `packages` instead of a dry run, so they don't show up in `calls()`.
+ * Tertiary package managers are only searched through `tertiary`, never the real system.
+ */
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    packages: RefCell<Vec<QueryResult>>,
+    pub output: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    pub tertiary: Vec<TertiaryPkg>,
+    calls: RefCell<Vec<String>>,
+}
+
+/**
+ * Package for FakeBackend, at version 1.0_1.
+ * Not behind cfg(test), so the tests of styx, lethe and cocytus can use it too.
+ */
+pub fn fake_pkg(pkg_name: &str, is_installed: bool) -> QueryResult {
+    return QueryResult {
+        is_installed,
+        pkg_name: pkg_name.into(),
+        pkg_version: "1.0_1".into(),
+        ..Default::default()
+    };
+}
+
+impl FakeBackend {
+    pub fn new(packages: Vec<QueryResult>) -> FakeBackend {
+        return FakeBackend { packages: RefCell::new(packages), ..Default::default() };
+    }
+    pub fn calls(&self) -> Vec<String> {
+        //! Transactions run so far, formatted as "{action} {pkgs}".
+        return self.calls.borrow().clone();
+    }
+    fn transaction(&self, action: &str, pkgs: &[&str], do_dry_run: bool, installed: Option<bool>) -> io::Result<Transaction> {
+        self.calls.borrow_mut().push(format!("{action} {}", pkgs.join(" ")).trim_end().to_string());
+
+        if let (false, Some(installed)) = (do_dry_run, installed) {
+            for pkg in self.packages.borrow_mut().iter_mut() {
+                if pkgs.is_empty() || pkgs.contains(&pkg.pkg_name.as_str()) {
+                    pkg.is_installed = installed;
+                }
+            }
+        }
+        return Ok(Transaction::from_output(&format!("fake {action}"), &self.output, &self.stderr, self.exit_code));
+    }
+    fn plan(&self, action: PlanAction, pkgs: &[&str]) -> TransactionPlan {
+        //! Only the packages asked for, without sizes. Not recorded in calls().
+        let entries = self.packages.borrow().iter()
+            .filter(|p| pkgs.iter().any(|x| pattern_name(x) == p.pkg_name))
+            .filter(|p| p.is_installed == (action == PlanAction::Remove))
+            .map(|p| PlanEntry {
+                action,
+                pkg_name: p.pkg_name.to_owned(),
+                old_version: None,
+                new_version: p.pkg_version.clone(),
+                installed_size: None,
+                download_size: None,
+            })
+            .collect();
+        return TransactionPlan { entries, errors: Vec::new() };
+    }
+}
+
+impl PackageBackend for FakeBackend {
+    fn list_available(&self) -> io::Result<Vec<QueryResult>> {
+        return Ok(self.packages.borrow().clone());
+    }
+    fn list_installed(&self) -> io::Result<Vec<QueryResult>> {
+        return Ok(self.packages.borrow().iter().filter(|p| p.is_installed).cloned().collect());
+    }
+    fn info(&self, pkg_name: &str) -> io::Result<PackageInfo> {
+        return match self.packages.borrow().iter().find(|p| p.pkg_name == pkg_name) {
+            Some(pkg) => Ok(PackageInfo::from(pkg)),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("Package not found: '{pkg_name}'"))),
+        };
+    }
+    fn install(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction> {
+        return self.transaction("install", pkgs, do_dry_run, Some(true));
+    }
+    fn remove(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction> {
+        return self.transaction("remove", pkgs, do_dry_run, Some(false));
+    }
+    fn update(&self, pkgs: &[&str], do_dry_run: bool) -> io::Result<Transaction> {
+        return self.transaction("update", pkgs, do_dry_run, None);
+    }
+    fn search_tertiary(&self, search_terms: &[&str]) -> Vec<Vec<TertiaryPkg>> {
+        return query_managers(search_terms, &self.tertiary, &[]);
+    }
+    fn plan_install(&self, pkgs: &[&str]) -> io::Result<TransactionPlan> {
+        return Ok(self.plan(PlanAction::Install, pkgs));
+    }
+    fn plan_remove(&self, pkgs: &[&str]) -> io::Result<TransactionPlan> {
+        return Ok(self.plan(PlanAction::Remove, pkgs));
+    }
+}
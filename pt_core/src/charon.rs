@@ -0,0 +1,285 @@
+/*!
+ * Reads charon's index of the programs it installed, stored in the mythos data dir, and drives
+ * charon to install or uninstall them.
+ *
+ * index.charon -> [pkg_name] version = "...", description = "...", source = "<dir>"
+ * <pkg_name>.charon (manifest) -> info = { version = "...", description = "...", source = "." }
+ *
+ * Programs are installed with `charon <manifest>` and removed with `charon --uninstall <pkg_name>`.
+ *
+ * A program is outdated if its manifest or Cargo.toml has a newer version than the one in index.charon.
+ * Versions are compared the same way xbps compares them, see PkgVersion.
+ *
+ * index.charon is only read once per command, see CharonIndex::get().
+ */
+use std::{fs, io, path::{Path, PathBuf}, sync::OnceLock};
+
+use mythos_core::{dirs, printerror, printinfo};
+use toml::Value;
+
+use crate::{utils::run_cmd, version::PkgVersion, PkgOrigin, QueryResult};
+
+pub const CHARON_INDEX: &str = "charon/index.charon";
+const MANIFEST_EXT: &str = "charon";
+
+static INDEX: OnceLock<CharonIndex> = OnceLock::new();
+
+/**
+ * Parsed index.charon. Top level tables are programs, anything else is ignored.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct CharonIndex {
+    table: toml::Table,
+}
+
+/**
+ * The info table of a .charon manifest.
+ * source: Directory the program is built from, relative to the manifest.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CharonManifest {
+    pub path: PathBuf,
+    pub version: String,
+    pub description: String,
+    pub source: PathBuf,
+}
+
+impl CharonManifest {
+    pub fn load(path: &Path) -> io::Result<CharonManifest> {
+        return CharonManifest::parse(&fs::read_to_string(path)?, path);
+    }
+    pub fn parse(contents: &str, path: &Path) -> io::Result<CharonManifest> {
+        let table: toml::Table = toml::from_str(contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let info = match table.get("info").and_then(|x| x.as_table()) {
+            Some(info) => info,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has no info table", path.display()))),
+        };
+        let string = |key: &str| if let Some(Value::String(x)) = info.get(key) { x.to_owned() } else { "".into() };
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        return Ok(CharonManifest {
+            path: path.to_path_buf(),
+            version: string("version"),
+            description: string("description"),
+            source: dir.join(string("source")),
+        });
+    }
+}
+
+/**
+ * A charon program whose source tree has a newer version than the one installed.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutdatedProgram {
+    pub pkg_name: String,
+    pub installed_version: PkgVersion,
+    pub source_version: PkgVersion,
+    pub manifest: PathBuf,
+}
+
+impl CharonIndex {
+    pub fn get() -> &'static CharonIndex {
+        //! Index of this system, read the first time it is needed.
+        return INDEX.get_or_init(CharonIndex::load);
+    }
+    pub fn load() -> CharonIndex {
+        //! Empty if there is no index, or it can't be read.
+        let path = match dirs::get_path(dirs::MythosDir::Data, CHARON_INDEX) {
+            Some(path) => path,
+            None => return CharonIndex::default(),
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(msg) => {
+                printerror!("Could not load charon file: {msg}");
+                return CharonIndex::default();
+            }
+        };
+        return match CharonIndex::parse(&contents) {
+            Ok(index) => index,
+            Err(msg) => {
+                printerror!("Could not parse charon file: {msg}");
+                CharonIndex::default()
+            }
+        };
+    }
+    pub fn parse(contents: &str) -> Result<CharonIndex, toml::de::Error> {
+        return Ok(CharonIndex { table: toml::from_str(contents)? });
+    }
+    pub fn programs(&self) -> Vec<QueryResult> {
+        return self.table.iter().filter_map(|(name, val)| {
+            let val = val.as_table()?;
+            let string = |key: &str| if let Some(Value::String(x)) = val.get(key) { x.to_owned() } else { "".into() };
+            Some(QueryResult {
+                is_installed: true,
+                pkg_name: name.to_owned(),
+                pkg_version: PkgVersion::parse(&string("version")),
+                pkg_description: string("description"),
+                origin: PkgOrigin::Charon,
+                ..Default::default()
+            })
+        }).collect();
+    }
+    pub fn find_manifest(&self, pkg_name: &str) -> Option<PathBuf> {
+        /*!
+         * Find the .charon manifest pkg_name was installed from, using the source dir recorded in the index.
+         * Prefers <source>/<pkg_name>.charon, then any other manifest in source.
+         */
+        let source = self.table.get(pkg_name)?.get("source")?.as_str()?;
+        return manifest_in_dir(Path::new(source), pkg_name);
+    }
+    pub fn list_outdated(&self) -> Vec<OutdatedProgram> {
+        //! Every program with a newer version in its source tree. Programs without a manifest are skipped.
+        return self.programs().iter()
+            .filter_map(|pkg| check_outdated(&pkg.pkg_name, &pkg.pkg_version, &self.find_manifest(&pkg.pkg_name)?))
+            .collect();
+    }
+}
+
+pub fn list_charon() -> Vec<QueryResult> {
+    //! Every program installed by charon. Empty if there is no index.
+    return CharonIndex::get().programs();
+}
+pub fn find_manifest(pkg_name: &str) -> Option<PathBuf> {
+    return CharonIndex::get().find_manifest(pkg_name);
+}
+pub fn manifest_in_dir(dir: &Path, pkg_name: &str) -> Option<PathBuf> {
+    let path = dir.join(format!("{pkg_name}.{MANIFEST_EXT}"));
+    if path.is_file() {
+        return Some(path);
+    }
+    let mut manifests = fs::read_dir(dir).ok()?
+        .flatten()
+        .map(|x| x.path())
+        .filter(|x| x.extension().is_some_and(|ext| ext == MANIFEST_EXT))
+        .collect::<Vec<PathBuf>>();
+    manifests.sort();
+    return manifests.into_iter().next();
+}
+pub fn source_version(manifest: &CharonManifest) -> PkgVersion {
+    //! Newest of the manifest's version and the version in its source's Cargo.toml.
+    let manifest_version = PkgVersion::parse(&manifest.version);
+    let cargo_version = fs::read_to_string(manifest.source.join("Cargo.toml")).ok()
+        .and_then(|contents| toml::from_str::<toml::Table>(&contents).ok())
+        .and_then(|table| table.get("package")?.get("version")?.as_str().map(PkgVersion::parse));
+
+    return match cargo_version {
+        Some(version) if version > manifest_version => version,
+        _ => manifest_version,
+    };
+}
+pub fn check_outdated(pkg_name: &str, installed_version: &PkgVersion, manifest: &Path) -> Option<OutdatedProgram> {
+    //! Returns None if manifest can't be read, or the installed version is current.
+    let manifest = CharonManifest::load(manifest).ok()?;
+    let source_version = source_version(&manifest);
+    if source_version <= *installed_version {
+        return None;
+    }
+    return Some(OutdatedProgram {
+        pkg_name: pkg_name.into(),
+        installed_version: installed_version.clone(),
+        source_version,
+        manifest: manifest.path,
+    });
+}
+pub fn list_outdated() -> Vec<OutdatedProgram> {
+    return CharonIndex::get().list_outdated();
+}
+pub fn install_cmd(manifest: &Path) -> Vec<String> {
+    return vec!["charon".into(), manifest.to_string_lossy().to_string()];
+}
+pub fn uninstall_cmd(pkg_name: &str) -> Vec<String> {
+    return vec!["charon".into(), "--uninstall".into(), pkg_name.into()];
+}
+pub fn install(pkg_name: &str, do_dry_run: bool) -> io::Result<()> {
+    //! (Re)install pkg_name from its manifest.
+    let manifest = match find_manifest(pkg_name) {
+        Some(manifest) => manifest,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("Could not find .charon manifest for '{pkg_name}'"))),
+    };
+    // Make sure this is actually a manifest, before handing it to charon.
+    CharonManifest::load(&manifest)?;
+    return run_charon(&install_cmd(&manifest), do_dry_run);
+}
+pub fn uninstall(pkg_name: &str, do_dry_run: bool) -> io::Result<()> {
+    return run_charon(&uninstall_cmd(pkg_name), do_dry_run);
+}
+fn run_charon(cmd: &[String], do_dry_run: bool) -> io::Result<()> {
+    if do_dry_run {
+        printinfo!("Would run: {}", cmd.join(" "));
+        return Ok(());
+    }
+    let status = run_cmd(cmd)?;
+    if !status.success() {
+        return Err(io::Error::other(format!("'{}' exited with {status}", cmd.join(" "))));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{charon::*, scorer::score_results, SearchOpts};
+
+    const INDEX: &str = r#"
+[charon]
+version = "0.3.0"
+description = "Installer for programs built from source"
+
+[plutonian-shores]
+version = "0.1.0"
+"#;
+
+    #[test]
+    fn test_fuzzy_charon_search() {
+        let pkgs = CharonIndex::parse(INDEX).unwrap().programs();
+        assert_eq!(pkgs.len(), 2);
+        assert!(pkgs.iter().all(|p| p.origin == PkgOrigin::Charon && p.is_installed));
+
+        let res = score_results(&pkgs, "charn", &SearchOpts::default());
+        assert_eq!(res.iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>(), vec!["charon"]);
+        assert_eq!(res[0].pkg_version, "0.3.0");
+    }
+    #[test]
+    fn test_manifest() {
+        let dir = std::env::temp_dir().join("pt_core_test_charon_manifest");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("styx.charon"), "info = { version = \"0.3.0\", description = \"Wrapper for xbps-install\", source = \".\" }\nbin = []\n").unwrap();
+
+        let path = manifest_in_dir(&dir, "styx").unwrap();
+        let manifest = CharonManifest::load(&path).unwrap();
+        assert_eq!(manifest.version, "0.3.0");
+        assert_eq!(manifest.source, dir.join("."));
+        let index = CharonIndex::parse(&format!("[styx]\nversion = \"0.2.0\"\nsource = {:?}\n", dir.to_string_lossy())).unwrap();
+        assert_eq!(index.find_manifest("styx"), Some(path.to_owned()));
+        assert_eq!(index.list_outdated()[0].source_version, "0.3.0");
+        // Manifest named after a different program is still found.
+        assert_eq!(manifest_in_dir(&dir, "styx-bin"), Some(path));
+        assert_eq!(install_cmd(&dir.join("styx.charon")), vec!["charon".to_string(), dir.join("styx.charon").to_string_lossy().to_string()]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn test_check_outdated() {
+        let dir = std::env::temp_dir().join("pt_core_test_charon_outdated");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("styx.charon");
+        fs::write(&manifest, "info = { version = \"0.3.0\", source = \".\" }\n").unwrap();
+
+        assert!(check_outdated("styx", &"0.3.0".into(), &manifest).is_none());
+        // Same version by xbps' rules.
+        assert!(check_outdated("styx", &"0.3".into(), &manifest).is_none());
+        let outdated = check_outdated("styx", &"0.2.1".into(), &manifest).unwrap();
+        assert_eq!(outdated.source_version, "0.3.0");
+
+        // Cargo.toml was bumped, but the manifest wasn't.
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"styx\"\nversion = \"0.10.0\"\n").unwrap();
+        assert_eq!(check_outdated("styx", &"0.3.0".into(), &manifest).unwrap().source_version, "0.10.0");
+        // Release candidates sort before the release.
+        assert!(check_outdated("styx", &"0.10.0".into(), &manifest).is_none());
+        assert!(check_outdated("styx", &"0.10.0rc1".into(), &manifest).is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
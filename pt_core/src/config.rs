@@ -0,0 +1,77 @@
+/*!
+ * User configuration, shared by styx, lethe and cocytus.
+ * Read from plutonian-shores/config.toml inside of the mythos config dir.
+ *
+ * [search]
+ * scorer = "fuzzy"        # fuzzy, prefix, substring, levenshtein, regex
+ * threshold = 0.3
+ * descriptions = false
+ * name_weight = 0.7
+ * description_weight = 0.3
+ *
+ * # Searched in order when a package isn't in xbps, charon or flatpak.
+ * # {} is replaced by the search term or package name. Commands are split on whitespace, and are not run in a shell.
+ * [[external]]
+ * name = "cargo"
+ * search = "cargo search --limit 10 {}"
+ * regex = '^(?P<name>\S+) = "(?P<version>[^"]*)"\s*#\s*(?P<description>.*)$'
+ * install = "cargo install {}"
+ * remove = "cargo uninstall {}"
+ */
+use std::fs;
+
+use mythos_core::{dirs, printerror};
+use serde_derive::Deserialize;
+
+const CONFIG_PATH: &str = "plutonian-shores/config.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub external: Vec<ExternalConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchConfig {
+    pub scorer: Option<String>,
+    pub threshold: Option<f32>,
+    pub descriptions: Option<bool>,
+    pub name_weight: Option<f32>,
+    pub description_weight: Option<f32>,
+}
+
+/**
+ * A package manager declared by the user.
+ * regex: Applied to each line of the search command's output. Must have a 'name' group, may have a 'description' group.
+ */
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExternalConfig {
+    pub name: String,
+    pub search: String,
+    pub regex: String,
+    pub install: String,
+    pub remove: String,
+}
+
+impl Config {
+    pub fn load() -> Config {
+        //! Read config file. If it doesn't exist, or can't be parsed, use defaults.
+        let path = match dirs::get_path(dirs::MythosDir::Config, CONFIG_PATH) {
+            Some(path) => path,
+            None => return Config::default(),
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Config::default(),
+        };
+        return match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(msg) => {
+                printerror!("Could not parse config file: {msg}");
+                Config::default()
+            }
+        };
+    }
+}
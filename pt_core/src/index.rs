@@ -0,0 +1,90 @@
+/*!
+ * On-disk cache of the package listing, stored in the mythos data dir.
+ *
+ * Building the listing means decompressing every repodata file and reading pkgdb.
+ * The index records the mtime of each of those files, and is rebuilt whenever one changes.
+ */
+use std::{fs, io, path::{Path, PathBuf}, time::UNIX_EPOCH};
+
+use mythos_core::dirs;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{pkgdb::PKGDB_FILE, repodata::find_repodata_files, QueryResult};
+
+const INDEX_PATH: &str = "plutonian-shores/index.json";
+/// Bump whenever QueryResult gains a field the index should be rebuilt for.
+const INDEX_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SearchIndex {
+    #[serde(default)]
+    pub version: u32,
+    /// Files the index was built from, and their mtime in milliseconds.
+    pub sources: Vec<(PathBuf, u64)>,
+    pub packages: Vec<QueryResult>,
+}
+
+impl SearchIndex {
+    pub fn new(db_dir: &Path, packages: Vec<QueryResult>) -> SearchIndex {
+        return SearchIndex { version: INDEX_VERSION, sources: source_mtimes(db_dir), packages };
+    }
+    pub fn load() -> Option<SearchIndex> {
+        //! Read the saved index. Returns None if it doesn't exist or can't be parsed.
+        let path = dirs::get_path(dirs::MythosDir::Data, INDEX_PATH)?;
+        let contents = fs::read(path).ok()?;
+        return serde_json::from_slice(&contents).ok();
+    }
+    pub fn save(&self) -> io::Result<()> {
+        let path = match dirs::get_path(dirs::MythosDir::Data, INDEX_PATH) {
+            Some(path) => path,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "Could not find mythos data dir")),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        return fs::write(path, serde_json::to_vec(self)?);
+    }
+    pub fn is_current(&self, db_dir: &Path) -> bool {
+        //! Index is current if none of its source files were added, removed, or modified, and it uses the current format.
+        return self.version == INDEX_VERSION && self.sources == source_mtimes(db_dir);
+    }
+}
+
+pub fn source_mtimes(db_dir: &Path) -> Vec<(PathBuf, u64)> {
+    //! mtime of every repodata file in db_dir, plus pkgdb. Missing files are skipped.
+    let mut files = find_repodata_files(db_dir);
+    files.push(PathBuf::from(PKGDB_FILE));
+
+    return files.into_iter().filter_map(|file| {
+        let mtime = fs::metadata(&file).ok()?.modified().ok()?;
+        let millis = mtime.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+        Some((file, millis))
+    }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::*;
+
+    #[test]
+    fn test_index_invalidation() {
+        let db_dir = std::env::temp_dir().join("pt_core_test_index_invalidation");
+        let _ = fs::remove_dir_all(&db_dir);
+        fs::create_dir_all(db_dir.join("repo_a")).unwrap();
+        fs::write(db_dir.join("repo_a/x86_64-repodata"), "").unwrap();
+
+        let index = SearchIndex::new(&db_dir, vec![]);
+        assert!(index.is_current(&db_dir));
+
+        // New repository was synced.
+        fs::create_dir_all(db_dir.join("repo_b")).unwrap();
+        fs::write(db_dir.join("repo_b/x86_64-repodata"), "").unwrap();
+        assert!(!index.is_current(&db_dir));
+
+        // Written by an older version.
+        let index = SearchIndex { version: 0, ..SearchIndex::new(&db_dir, vec![]) };
+        assert!(!index.is_current(&db_dir));
+
+        let _ = fs::remove_dir_all(&db_dir);
+    }
+}
@@ -1,13 +1,38 @@
+pub mod backend;
+pub mod charon;
+pub mod config;
+pub mod index;
+pub mod ngram;
+pub mod package_info;
+pub mod pattern;
+pub mod pkgdb;
+pub mod plan;
 pub mod query; 
 pub mod query_result;
+pub mod repodata;
+pub mod scorer;
+pub mod search_opts;
+pub mod tertiary;
+pub mod version;
+pub mod xbps_cmd;
+pub mod xbps_events;
+pub mod xbps_output;
 mod utils;
 
+use backend::PackageBackend;
+use pattern::PatternKind;
+use scorer::ScorerKind;
+use tertiary::TertiaryPkg;
+use version::{PkgSpec, PkgVersion};
 use mythos_core::{cli::get_cli_input, printfatal, printinfo, printwarn};
 use serde_derive::{Deserialize, Serialize};
 
+// Packages xbps or charon handle, and packages handed off to a tertiary package manager.
+type SplitPkgs = (Vec<QueryResult>, Vec<TertiaryPkg>);
+
 /* FUNCTIONS */
 pub fn parse_output(output: Vec<u8>) -> String {
-    return output.iter().map(|x| (*x as char)).collect::<String>().trim().to_string();
+    return String::from_utf8_lossy(&output).trim().to_string();
 }
 pub fn xbps_args_to_string(xbps_args: &Vec<String>) -> String {
     if xbps_args.len() == 0 {
@@ -20,7 +45,7 @@ pub fn xbps_args_to_string(xbps_args: &Vec<String>) -> String {
         acc + x.trim_start_matches("-")
     });
 }
-pub fn validate_pkgs<T>(search_terms: T) -> Option<Vec<QueryResult>>  where T: Iterator<Item = String>{
+pub fn validate_pkgs<T>(backend: &dyn PackageBackend, search_terms: T, opts: &SearchOpts) -> Result<Option<Vec<QueryResult>>, Vec<String>>  where T: Iterator<Item = String>{
     /*!
      * Iterate over pkgs, searching for each one in repo. 
      * Allows user to select from results or remove it.
@@ -28,14 +53,54 @@ pub fn validate_pkgs<T>(search_terms: T) -> Option<Vec<QueryResult>>  where T: I
      * Returns None if all packages are removed or user exits.
      *
      * If not do_selection: Only ensure packages exist, but don't allow user to select new ones.
+     *
+     * If opts.exact: No selection is done. Returns Err with a message for each package that doesn't exist.
+     *
+     * Terms with version constraints (foo>=1.2, foo<2.0, foo-1.2_1) aren't searched for.
+     * Returns Err with a message for each one that no available or installed version satisfies.
+     *
+     * Packages only found in a tertiary package manager are included, with an External origin.
+     */
+    return validate_pkgs_tertiary(backend, search_terms, opts).map(|res| res.map(|(mut pkgs, tertiary)| {
+        pkgs.extend(tertiary.iter().map(QueryResult::from));
+        pkgs
+    }));
+}
+pub fn validate_pkgs_tertiary<T>(backend: &dyn PackageBackend, search_terms: T, opts: &SearchOpts) -> Result<Option<SplitPkgs>, Vec<String>>  where T: Iterator<Item = String>{
+    /*!
+     * Same as validate_pkgs(), but packages only found in a tertiary package manager are returned
+     * separately, so they can be handed off to it.
      */
     let mut output: Vec<QueryResult> = Vec::new();
+    let mut tertiary: Vec<TertiaryPkg> = Vec::new();
+    let search_terms = search_terms.collect::<Vec<String>>();
+    let search_terms = search_terms.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+
+    // Terms with version constraints (foo>=1.2) must be satisfied, there is nothing to select from.
+    let specs = search_terms.iter().filter_map(|term| PkgSpec::parse(term)).collect::<Vec<PkgSpec>>();
+    let search_terms = search_terms.into_iter().filter(|term| PkgSpec::parse(term).is_none()).collect::<Vec<&str>>();
+    if specs.len() > 0 {
+        output.extend(Query::query_specs(backend, &specs, opts)?);
+    }
 
-    for term in search_terms {
-        let query = match Query::query(&term) {
+    if opts.exact {
+        return match Query::query_exact(backend, &search_terms, opts) {
+            Ok(pkgs) => Ok(Some(([output, pkgs].concat(), tertiary))),
+            Err(missing) => Err(missing.iter().map(|term| format!("Package not found: '{term}'")).collect()),
+        };
+    }
+    let queries = Query::query_many(backend, &search_terms, opts);
+
+    for (term, query) in search_terms.iter().zip(queries) {
+        let query = match query {
             Ok(res) => res,
-            Err(QueryError::NotFound(msg)) | Err(QueryError::TertiaryList(msg)) => {
+            Err(QueryError::NotFound(msg)) => {
+                printwarn!("{msg}");
+                continue;
+            },
+            Err(QueryError::TertiaryList(msg, pkgs)) => {
                 printwarn!("{msg}");
+                tertiary.extend(pkgs);
                 continue;
             }
         };
@@ -56,7 +121,7 @@ pub fn validate_pkgs<T>(search_terms: T) -> Option<Vec<QueryResult>>  where T: I
         // Get and validate user selection.
         let user_input = get_user_selection(msg, 2);
         if user_input == 0 {
-            return None;
+            return Ok(None);
         }
         if user_input == 2 {
             printinfo!("Removed {term}");
@@ -78,7 +143,7 @@ pub fn validate_pkgs<T>(search_terms: T) -> Option<Vec<QueryResult>>  where T: I
         output.extend(selection.unwrap().results);
     }
 
-    return Some(output);
+    return Ok(Some((output, tertiary)));
 }
 
 pub fn get_user_selection(msg: &str, max_val: usize) -> usize {
@@ -112,13 +177,66 @@ pub struct Query {
     longest_name: usize,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct QueryResult {
     pub is_installed: bool,
     pub pkg_name: String,
-    pub pkg_version: String,
+    pub pkg_version: PkgVersion,
     pub pkg_description: String,
     pub score: i32,
+    /// Installed size in bytes. 0 if unknown.
+    #[serde(default)]
+    pub pkg_size: u64,
+    /// Run time dependencies, as xbps patterns (e.g. glibc>=2.36_1).
+    #[serde(default)]
+    pub pkg_depends: Vec<String>,
+    #[serde(default)]
+    pub pkg_homepage: String,
+    #[serde(default)]
+    pub origin: PkgOrigin,
+    /// Virtual packages this package provides, as xbps pkgvers (e.g. awk-0_1).
+    #[serde(default)]
+    pub pkg_provides: Vec<String>,
+    /// Shared libraries this package provides (e.g. libpython3.12.so.1.0).
+    #[serde(default)]
+    pub pkg_shlib_provides: Vec<String>,
+    /// Set if this package was found because it provides the virtual package that was searched for.
+    #[serde(skip)]
+    pub virtual_name: Option<String>,
+    /// Version constraint this package was picked by, e.g. foo>=1.2. None for plain names.
+    #[serde(skip)]
+    pub pkg_spec: Option<PkgSpec>,
+}
+/**
+ * Where a QueryResult was found.
+ * Xbps: URL of the repository. Empty if unknown.
+ * External: Name of the package manager, e.g. flatpak.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PkgOrigin {
+    Xbps(String),
+    Charon,
+    External(String),
+}
+/**
+ * Controls how search terms are matched against packages.
+ *
+ * threshold: Minimum score [0, 1] a package must get to be included in results.
+ * scorer: Strategy used to score packages.
+ * search_descriptions: Also score pkg_description. Every word of a search term must then match.
+ * name_weight, description_weight: Share of the score given to name and description matches.
+ * exact: Only match package names verbatim. Missing packages are an error, rather than a prompt.
+ * pattern: Treat every search term as this kind of pattern. If None, patterns are detected by syntax.
+ */
+#[derive(Debug, Clone)]
+pub struct SearchOpts {
+    pub threshold: f32,
+    pub scorer: ScorerKind,
+    pub search_descriptions: bool,
+    pub name_weight: f32,
+    pub description_weight: f32,
+    pub exact: bool,
+    pub pattern: Option<PatternKind>,
 }
 /**
  * TertiaryList: Package was found in tertiary list and can be installed using the contained pkg
@@ -127,6 +245,6 @@ pub struct QueryResult {
  */
 #[derive(Debug)]
 pub enum QueryError{
-    TertiaryList(String),
+    TertiaryList(String, Vec<TertiaryPkg>),
     NotFound(String),
 }
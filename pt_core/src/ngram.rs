@@ -0,0 +1,220 @@
+/*!
+ * Trigram inverted index over package names.
+ *
+ * fuzzy_compare scores two names by the trigrams they share, so any name sharing no trigram with
+ * the search term scores 0. The index maps each trigram to the names containing it, which narrows a
+ * search down to the names worth scoring.
+ *
+ * Virtual packages (e.g. awk, java-runtime) have no entry of their own. IndexedListing maps each
+ * virtual name to the packages providing it.
+ */
+use std::{cell::OnceCell, collections::HashMap, iter};
+
+use crate::{pattern::PatternKind, scorer::{score_results, ScorerKind, TermScorer}, utils::{description_words, split_name_block}, QueryResult, SearchOpts};
+
+type Trigram = (char, char, char);
+
+pub fn trigrams(s: &str) -> impl Iterator<Item = Trigram> + '_ {
+    //! Same padding as rust_fuzzy_search: "ab" -> "  a", " ab", "ab ".
+    let it_1 = iter::once(' ').chain(iter::once(' ')).chain(s.chars());
+    let it_2 = iter::once(' ').chain(s.chars());
+    let it_3 = s.chars().chain(iter::once(' '));
+    return it_1.zip(it_2).zip(it_3).map(|((a, b), c)| (a, b, c));
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NgramIndex {
+    postings: HashMap<Trigram, Vec<usize>>,
+    len: usize,
+}
+
+impl NgramIndex {
+    pub fn new<'a, T>(names: T) -> NgramIndex where T: Iterator<Item = &'a str> {
+        let mut index = NgramIndex::default();
+        for name in names {
+            index.push(&[name]);
+        }
+        return index;
+    }
+    pub fn push(&mut self, words: &[&str]) {
+        //! Add an entry made up of words. Each word is padded separately, like fuzzy_compare does.
+        let i = self.len;
+        for word in words {
+            for trigram in trigrams(word) {
+                let posting = self.postings.entry(trigram).or_default();
+                // Entries are added in order, so a repeated trigram can only be at the end.
+                if posting.last() != Some(&i) {
+                    posting.push(i);
+                }
+            }
+        }
+        self.len += 1;
+    }
+    pub fn candidates(&self, search_term: &str) -> Vec<usize> {
+        //! Indices of every name sharing at least one trigram with search_term, in ascending order.
+        let mut is_candidate = vec![false; self.len];
+        for trigram in trigrams(search_term) {
+            if let Some(posting) = self.postings.get(&trigram) {
+                for i in posting {
+                    is_candidate[*i] = true;
+                }
+            }
+        }
+        return is_candidate.iter().enumerate().filter(|(_, x)| **x).map(|(i, _)| i).collect();
+    }
+}
+
+/**
+ * A package listing and the trigram index of its names.
+ * Build once, then search as many times as needed, with any SearchOpts.
+ *
+ * desc_index: Also indexes description words. Only built the first time descriptions are searched.
+ * providers: Virtual package name -> indices of the packages providing it.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct IndexedListing {
+    listing: Vec<QueryResult>,
+    index: NgramIndex,
+    desc_index: OnceCell<NgramIndex>,
+    providers: HashMap<String, Vec<usize>>,
+}
+
+impl IndexedListing {
+    pub fn new(listing: Vec<QueryResult>) -> IndexedListing {
+        let index = NgramIndex::new(listing.iter().map(|p| p.pkg_name.as_str()));
+        let mut providers: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, pkg) in listing.iter().enumerate() {
+            for pkgver in pkg.pkg_provides.iter() {
+                let (virtual_name, _) = split_name_block(pkgver);
+                providers.entry(virtual_name).or_default().push(i);
+            }
+        }
+        return IndexedListing { listing, index, desc_index: OnceCell::new(), providers };
+    }
+    fn desc_index(&self) -> &NgramIndex {
+        return self.desc_index.get_or_init(|| {
+            let mut desc_index = NgramIndex::default();
+            for pkg in self.listing.iter() {
+                let words = description_words(&pkg.pkg_description);
+                let mut words = words.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+                words.push(&pkg.pkg_name);
+                desc_index.push(&words);
+            }
+            desc_index
+        });
+    }
+    pub fn search(&self, search_term: &str, opts: &SearchOpts) -> Vec<QueryResult> {
+        //! Score candidates against search_term. Results are the same as scoring the entire listing.
+        // Entries sharing no trigrams score 0, which only passes a threshold of 0 or less.
+        // Other scorers and patterns don't rely on trigrams, so the index can't narrow their candidates.
+        if opts.threshold <= 0.0 || opts.scorer != ScorerKind::Fuzzy || PatternKind::of(search_term, opts).is_some() {
+            return score_results(&self.listing, search_term, opts);
+        }
+        let candidates = if opts.search_descriptions {
+            // Every word must match, so only keep entries that are candidates for all of them.
+            let desc_index = self.desc_index();
+            let mut candidates: Option<Vec<usize>> = None;
+            for token in search_term.split_whitespace() {
+                let mut token_candidates = desc_index.candidates(token);
+                token_candidates.extend(desc_index.candidates(&token.to_lowercase()));
+                token_candidates.sort();
+                token_candidates.dedup();
+                candidates = Some(match candidates {
+                    Some(mut x) => { x.retain(|i| token_candidates.binary_search(i).is_ok()); x },
+                    None => token_candidates,
+                });
+            }
+            candidates.unwrap_or_default()
+        } else {
+            self.index.candidates(search_term)
+        };
+
+        let scorer = TermScorer::new(search_term, opts);
+        return candidates.into_iter().filter_map(|i| {
+            let res = &self.listing[i];
+            let score = scorer.score(res)?;
+            Some(QueryResult { score, ..res.clone() })
+        }).collect();
+    }
+    pub fn providers(&self, virtual_name: &str) -> Vec<QueryResult> {
+        //! Every package providing virtual_name, marked with it. Scored as exact matches.
+        return match self.providers.get(virtual_name) {
+            Some(providers) => providers.iter()
+                .map(|i| QueryResult { score: 100, virtual_name: Some(virtual_name.into()), ..self.listing[*i].clone() })
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+    pub fn listing(&self) -> &[QueryResult] {
+        return &self.listing;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ngram::*, scorer::score_results};
+
+    fn listing() -> Vec<QueryResult> {
+        return [
+            ("blender", "3D graphics creation suite"),
+            ("blend2d", "High performance 2D vector graphics engine"),
+            ("vim", "Vi IMproved"),
+            ("neovim", "Fork of Vim aiming to improve user experience, plugins and GUIs"),
+            ("python3", "Python programming language"),
+            ("python3-devel", "Python programming language - development files"),
+            ("font-misc-misc", "Standard X11 fonts"),
+            ("zathura-pdf-mupdf", "PDF support for zathura (using mupdf)"),
+            ("zathura", "Highly customizable and functional document viewer"),
+        ]
+            .iter()
+            .map(|(name, desc)| QueryResult { pkg_name: name.to_string(), pkg_description: desc.to_string(), ..Default::default() })
+            .collect();
+    }
+    fn assert_same_results(terms: &[&str], opts: &SearchOpts) {
+        let indexed = IndexedListing::new(listing());
+        for term in terms {
+            let expected = score_results(&listing(), term, opts);
+            let actual = indexed.search(term, opts);
+            assert_eq!(
+                expected.iter().map(|p| (&p.pkg_name, p.score)).collect::<Vec<_>>(),
+                actual.iter().map(|p| (&p.pkg_name, p.score)).collect::<Vec<_>>(),
+                "search term: '{term}'"
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_results_as_full_scan() {
+        assert_same_results(&["blender", "blende", "bledner", "vim", "pyton", "font", "z", ""], &SearchOpts::default());
+    }
+    #[test]
+    fn test_same_results_as_full_scan_with_descriptions() {
+        let opts = SearchOpts { search_descriptions: true, ..Default::default() };
+        assert_same_results(&["pdf viewer", "graphics", "Python devel", "vim", "zzz", ""], &opts);
+    }
+    #[test]
+    fn test_one_listing_for_any_opts() {
+        let indexed = IndexedListing::new(listing());
+        let opts = SearchOpts { search_descriptions: true, ..Default::default() };
+        assert_eq!(indexed.search("blende", &SearchOpts::default())[0].pkg_name, "blender");
+        assert_eq!(indexed.search("document viewer", &opts)[0].pkg_name, "zathura");
+        assert_eq!(indexed.search("blende", &SearchOpts::default())[0].pkg_name, "blender");
+    }
+    #[test]
+    fn test_exact_match_scores_100() {
+        let indexed = IndexedListing::new(listing());
+        let res = indexed.search("vim", &SearchOpts::default());
+        assert_eq!(res.iter().find(|p| p.pkg_name == "vim").unwrap().score, 100);
+    }
+    #[test]
+    fn test_description_search_requires_every_word() {
+        let opts = SearchOpts { search_descriptions: true, ..Default::default() };
+        let indexed = IndexedListing::new(listing());
+        let names = indexed.search("document viewer", &opts).into_iter().map(|p| p.pkg_name).collect::<Vec<String>>();
+        assert_eq!(names, vec!["zathura"]);
+
+        let res = indexed.search("pdf viewer", &opts);
+        assert!(res.iter().all(|p| p.score < 100));
+        assert!(indexed.search("graphics spreadsheet", &opts).is_empty());
+    }
+}
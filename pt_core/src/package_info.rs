@@ -0,0 +1,177 @@
+/*!
+ * Details about a single package, parsed from xbps-query -S (installed) or -RS (repository).
+ *
+ * Output is one "key: value" property per line. Properties with multiple values (e.g. run_depends)
+ * have an empty value, and list one value per indented line after the key.
+ */
+use std::fmt::Display;
+
+use crate::{utils::split_name_block, version::PkgVersion, QueryResult};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackageInfo {
+    pub pkg_name: String,
+    pub pkg_version: PkgVersion,
+    pub pkg_description: String,
+    pub architecture: String,
+    /// Human readable, as printed by xbps (e.g. 393MB).
+    pub installed_size: String,
+    /// Size of the binary package. Human readable, as printed by xbps.
+    pub download_size: String,
+    pub license: String,
+    pub maintainer: String,
+    pub homepage: String,
+    pub build_date: String,
+    /// Only known for installed packages.
+    pub install_date: String,
+    pub repository: String,
+    pub shlib_provides: Vec<String>,
+    pub shlib_requires: Vec<String>,
+    pub run_depends: Vec<String>,
+    pub conf_files: Vec<String>,
+}
+
+impl PackageInfo {
+    pub fn parse(output: &str) -> PackageInfo {
+        //! Unknown properties are ignored.
+        let mut info = PackageInfo::default();
+        let mut list: Option<&mut Vec<String>> = None;
+
+        for line in output.lines() {
+            // Value of a multi-value property.
+            if line.starts_with(char::is_whitespace) {
+                if let Some(list) = list.as_mut() {
+                    if !line.trim().is_empty() {
+                        list.push(line.trim().to_string());
+                    }
+                }
+                continue;
+            }
+            list = None;
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim().to_string()),
+                None => continue,
+            };
+            match key {
+                "pkgver" => {
+                    let (pkg_name, pkg_version) = split_name_block(&value);
+                    info.pkg_name = pkg_name;
+                    info.pkg_version = pkg_version;
+                },
+                "short_desc" => info.pkg_description = value,
+                "architecture" => info.architecture = value,
+                "installed_size" => info.installed_size = value,
+                "filename-size" => info.download_size = value,
+                "license" => info.license = value,
+                "maintainer" => info.maintainer = value,
+                "homepage" => info.homepage = value,
+                "build-date" => info.build_date = value,
+                "install-date" => info.install_date = value,
+                "repository" => info.repository = value,
+                "shlib-provides" => list = Some(&mut info.shlib_provides),
+                "shlib-requires" => list = Some(&mut info.shlib_requires),
+                "run_depends" => list = Some(&mut info.run_depends),
+                "conf_files" => list = Some(&mut info.conf_files),
+                _ => (),
+            }
+        }
+        return info;
+    }
+}
+impl From<&QueryResult> for PackageInfo {
+    fn from(value: &QueryResult) -> Self {
+        return PackageInfo {
+            pkg_name: value.pkg_name.to_owned(),
+            pkg_version: value.pkg_version.to_owned(),
+            pkg_description: value.pkg_description.to_owned(),
+            homepage: value.pkg_homepage.to_owned(),
+            run_depends: value.pkg_depends.to_owned(),
+            ..Default::default()
+        };
+    }
+}
+impl Display for PackageInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //! Name and description, followed by every known property. Unknown ones are left out.
+        writeln!(f, "{}-{}\n{}\n", self.pkg_name, self.pkg_version, self.pkg_description)?;
+
+        let fields = [
+            ("Architecture", &self.architecture),
+            ("Installed size", &self.installed_size),
+            ("Download size", &self.download_size),
+            ("License", &self.license),
+            ("Maintainer", &self.maintainer),
+            ("Homepage", &self.homepage),
+            ("Build date", &self.build_date),
+            ("Install date", &self.install_date),
+            ("Repository", &self.repository),
+        ];
+        for (name, value) in fields.iter().filter(|(_, value)| !value.is_empty()) {
+            writeln!(f, "{:<16}{value}", format!("{name}:"))?;
+        }
+
+        let lists = [
+            ("Run depends", &self.run_depends),
+            ("Shlib provides", &self.shlib_provides),
+            ("Shlib requires", &self.shlib_requires),
+            ("Conf files", &self.conf_files),
+        ];
+        for (name, values) in lists.iter().filter(|(_, values)| !values.is_empty()) {
+            writeln!(f, "\n{name}:")?;
+            for value in values.iter() {
+                writeln!(f, "  {value}")?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::package_info::*;
+
+    const XBPS_QUERY_RS: &str = "architecture: x86_64
+build-date: 2024-01-31 18:02 UTC
+filename-sha256: 5a0b3b3c2d3e0c8f
+filename-size: 98MB
+homepage: https://www.blender.org
+installed_size: 393MB
+license: GPL-2.0-or-later
+maintainer: Orphaned <orphan@voidlinux.org>
+pkgver: blender-4.0.2_1
+repository: https://repo-default.voidlinux.org/current
+run_depends:
+\tglibc>=2.36_1
+\tpython3>=3.12_1
+shlib-provides:
+\tlibcycles.so
+shlib-requires:
+\tlibc.so.6
+\tlibpython3.12.so.1.0
+short_desc: 3D graphics creation suite
+";
+
+    #[test]
+    fn test_parse() {
+        let info = PackageInfo::parse(XBPS_QUERY_RS);
+        assert_eq!(info.pkg_name, "blender");
+        assert_eq!(info.architecture, "x86_64");
+        assert_eq!(info.download_size, "98MB");
+        assert_eq!(info.installed_size, "393MB");
+        assert_eq!(info.maintainer, "Orphaned <orphan@voidlinux.org>");
+        assert_eq!(info.build_date, "2024-01-31 18:02 UTC");
+        assert_eq!(info.run_depends, vec!["glibc>=2.36_1", "python3>=3.12_1"]);
+        assert_eq!(info.shlib_provides, vec!["libcycles.so"]);
+        assert_eq!(info.shlib_requires, vec!["libc.so.6", "libpython3.12.so.1.0"]);
+        assert!(info.conf_files.is_empty());
+        assert_eq!(info.pkg_description, "3D graphics creation suite");
+    }
+    #[test]
+    fn test_display_skips_unknown_fields() {
+        let info = PackageInfo::parse(XBPS_QUERY_RS).to_string();
+        assert!(info.starts_with("blender-4.0.2_1\n3D graphics creation suite\n"));
+        assert!(info.contains("License:        GPL-2.0-or-later\n"));
+        assert!(!info.contains("Install date"));
+        assert!(info.contains("\nShlib requires:\n  libc.so.6\n"));
+    }
+}
@@ -0,0 +1,103 @@
+/*!
+ * Glob and regex search terms.
+ *
+ * A pattern matches every package whose name it matches, instead of fuzzy finding the closest ones.
+ * Patterns are either set with --glob/--regex, or detected by their syntax:
+ * glob: Contains '*', '?' or '['. e.g. font-*
+ * regex: Starts with '^' or ends with '$'. e.g. ^python3-.*-devel$
+ */
+use crate::{scorer::{RegexScorer, Scorer}, SearchOpts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternKind {
+    Glob,
+    Regex,
+}
+
+impl PatternKind {
+    pub fn detect(search_term: &str) -> Option<PatternKind> {
+        //! Package names can contain '+' and '.', so only unambiguous syntax is detected.
+        if search_term.starts_with('^') || search_term.ends_with('$') {
+            return Some(PatternKind::Regex);
+        }
+        if search_term.contains(['*', '?', '[']) {
+            return Some(PatternKind::Glob);
+        }
+        return None;
+    }
+    pub fn of(search_term: &str, opts: &SearchOpts) -> Option<PatternKind> {
+        //! Pattern kind search_term should be treated as. None if it is a plain search term.
+        if opts.exact {
+            return None;
+        }
+        return opts.pattern.or_else(|| PatternKind::detect(search_term));
+    }
+    pub fn build(&self, search_term: &str) -> Box<dyn Scorer> {
+        //! Scores 0 for names that don't match.
+        return match self {
+            PatternKind::Glob => Box::new(RegexScorer::new(&glob_to_regex(search_term))),
+            PatternKind::Regex => Box::new(RegexScorer::new(search_term)),
+        };
+    }
+}
+
+pub fn glob_to_regex(glob: &str) -> String {
+    /*!
+     * Convert a shell glob to an anchored regex.
+     * '*' -> any chars, '?' -> one char, [abc] and [!abc] -> char classes.
+     * Everything else is matched literally.
+     */
+    let mut output = "^".to_string();
+    let mut chars = glob.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' => output += ".*",
+            '?' => output += ".",
+            '[' => {
+                output.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    output.push('^');
+                }
+                for ch in chars.by_ref() {
+                    if ch == ']' {
+                        break;
+                    }
+                    if ch == '\\' || ch == '[' {
+                        output.push('\\');
+                    }
+                    output.push(ch);
+                }
+                output.push(']');
+            },
+            _ => output += &regex::escape(&ch.to_string()),
+        }
+    }
+    output.push('$');
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(PatternKind::detect("font-*"), Some(PatternKind::Glob));
+        assert_eq!(PatternKind::detect("python3-[!d]*"), Some(PatternKind::Glob));
+        assert_eq!(PatternKind::detect("^python3-.*-devel$"), Some(PatternKind::Regex));
+        assert_eq!(PatternKind::detect("gtk+3"), None);
+        assert_eq!(PatternKind::detect("python3.12"), None);
+        assert_eq!(PatternKind::of("font-*", &SearchOpts { exact: true, ..Default::default() }), None);
+    }
+    #[test]
+    fn test_glob_to_regex() {
+        assert_eq!(glob_to_regex("font-*"), "^font\\-.*$");
+        assert_eq!(glob_to_regex("gtk+?"), "^gtk\\+.$");
+        assert_eq!(glob_to_regex("lib[!x]*"), "^lib[^x].*$");
+
+        let scorer = PatternKind::Glob.build("font-*-misc");
+        assert!(scorer.score("font-misc-misc") > 0.0);
+        assert_eq!(scorer.score("xfont-misc-misc"), 0.0);
+    }
+}
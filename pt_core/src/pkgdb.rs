@@ -0,0 +1,192 @@
+/*!
+ * Reads xbps' package database, which records every installed package.
+ *
+ * pkgdb-0.38.plist -> <dict> pkg_name -> <dict> properties
+ * Keys starting with '_' (e.g. _XBPS_ALTERNATIVES_) are xbps metadata, not packages.
+ */
+use std::{fs, io, path::Path};
+
+use crate::{utils::{pattern_name, plist_string, plist_strings, split_name_block}, version::PkgVersion, PkgOrigin, QueryResult};
+
+pub const PKGDB_FILE: &str = "/var/db/xbps/pkgdb-0.38.plist";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstalledPkg {
+    pub pkg_name: String,
+    pub pkg_version: PkgVersion,
+    pub pkg_description: String,
+    /// As written by xbps, e.g. "2024-01-31 18:02 UTC".
+    pub install_date: String,
+    /// Installed as a dependency, rather than by the user.
+    pub is_automatic: bool,
+    /// Excluded from updates (xbps-pkgdb -m hold).
+    pub is_held: bool,
+    /// Run time dependencies, as xbps patterns.
+    pub requirements: Vec<String>,
+    /// URL of the repository pkg was installed from.
+    pub repository: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PkgDb {
+    pkgs: Vec<InstalledPkg>,
+}
+
+impl PkgDb {
+    pub fn load() -> io::Result<PkgDb> {
+        return PkgDb::from_file(Path::new(PKGDB_FILE));
+    }
+    pub fn from_file(path: &Path) -> io::Result<PkgDb> {
+        return PkgDb::parse(&fs::read(path)?);
+    }
+    pub fn parse(pkgdb: &[u8]) -> io::Result<PkgDb> {
+        let value = plist::Value::from_reader_xml(pkgdb)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let dict = match value.as_dictionary() {
+            Some(dict) => dict,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "pkgdb is not a dictionary")),
+        };
+
+        let mut pkgs: Vec<InstalledPkg> = Vec::new();
+        for (name, props) in dict.iter() {
+            let props = match props.as_dictionary() {
+                Some(props) if !name.starts_with('_') => props,
+                _ => continue,
+            };
+            let (_, pkg_version) = split_name_block(&plist_string(props, "pkgver"));
+            let flag = |key: &str| props.get(key).and_then(|x| x.as_boolean()).unwrap_or(false);
+
+            pkgs.push(InstalledPkg {
+                pkg_name: name.to_owned(),
+                pkg_version,
+                pkg_description: plist_string(props, "short_desc"),
+                install_date: plist_string(props, "install-date"),
+                is_automatic: flag("automatic-install"),
+                is_held: flag("hold"),
+                requirements: plist_strings(props, "run_depends"),
+                repository: plist_string(props, "repository"),
+            });
+        }
+        return Ok(PkgDb { pkgs });
+    }
+
+    pub fn get<'a>(&'a self, pkg_name: &str) -> Option<&'a InstalledPkg> {
+        return self.pkgs.iter().find(|p| p.pkg_name == pkg_name);
+    }
+    pub fn is_installed(&self, pkg_name: &str) -> bool {
+        return self.get(pkg_name).is_some();
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, InstalledPkg> {
+        return self.pkgs.iter();
+    }
+    pub fn required_by<'a>(&'a self, pkg_name: &str) -> Vec<&'a InstalledPkg> {
+        //! Installed packages that list pkg_name as a requirement.
+        return self.pkgs.iter()
+            .filter(|p| p.requirements.iter().any(|r| pattern_name(r) == pkg_name))
+            .collect();
+    }
+    pub fn install_reason(&self, pkg_name: &str) -> Option<String> {
+        /*!
+         * Explain why pkg_name is installed.
+         * Returns None if it isn't.
+         */
+        let pkg = self.get(pkg_name)?;
+        let mut output = if pkg.is_automatic {
+            let required_by = self.required_by(pkg_name).iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>();
+            if required_by.len() == 0 {
+                format!("Installed automatically on {}, but is no longer required by any package", pkg.install_date)
+            } else {
+                format!("Installed automatically on {}, required by: {}", pkg.install_date, required_by.join(", "))
+            }
+        } else {
+            format!("Installed manually on {}", pkg.install_date)
+        };
+
+        if pkg.is_held {
+            output += " (held)";
+        }
+        return Some(output);
+    }
+}
+
+impl From<&InstalledPkg> for QueryResult {
+    fn from(value: &InstalledPkg) -> Self {
+        return QueryResult {
+            is_installed: true,
+            pkg_name: value.pkg_name.to_owned(),
+            pkg_version: value.pkg_version.to_owned(),
+            pkg_description: value.pkg_description.to_owned(),
+            pkg_depends: value.requirements.to_owned(),
+            origin: PkgOrigin::Xbps(value.repository.to_owned()),
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pkgdb::*;
+
+    const PKGDB: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple Computer//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>_XBPS_ALTERNATIVES_</key>
+	<dict>
+		<key>awk</key>
+		<array>
+			<string>gawk</string>
+		</array>
+	</dict>
+	<key>blender</key>
+	<dict>
+		<key>install-date</key>
+		<string>2024-01-31 18:02 UTC</string>
+		<key>hold</key>
+		<true/>
+		<key>pkgver</key>
+		<string>blender-4.0.2_1</string>
+		<key>run_depends</key>
+		<array>
+			<string>python3&gt;=3.12_1</string>
+		</array>
+		<key>repository</key>
+		<string>https://repo-default.voidlinux.org/current</string>
+		<key>state</key>
+		<string>installed</string>
+	</dict>
+	<key>python3</key>
+	<dict>
+		<key>automatic-install</key>
+		<true/>
+		<key>install-date</key>
+		<string>2024-01-31 18:01 UTC</string>
+		<key>pkgver</key>
+		<string>python3-3.12.1_1</string>
+		<key>state</key>
+		<string>installed</string>
+	</dict>
+</dict>
+</plist>"#;
+
+    #[test]
+    fn test_parse_pkgdb() {
+        let db = PkgDb::parse(PKGDB.as_bytes()).unwrap();
+        assert_eq!(db.iter().count(), 2);
+        assert!(!db.is_installed("_XBPS_ALTERNATIVES_"));
+
+        let blender = db.get("blender").unwrap();
+        assert!(blender.is_held);
+        assert!(!blender.is_automatic);
+        assert_eq!(blender.requirements, vec!["python3>=3.12_1"]);
+        assert_eq!(QueryResult::from(blender).origin, PkgOrigin::Xbps("https://repo-default.voidlinux.org/current".into()));
+        assert_eq!(db.get("python3").unwrap().pkg_version, "3.12.1_1");
+    }
+    #[test]
+    fn test_install_reason() {
+        let db = PkgDb::parse(PKGDB.as_bytes()).unwrap();
+        assert_eq!(db.install_reason("blender").unwrap(), "Installed manually on 2024-01-31 18:02 UTC (held)");
+        assert_eq!(db.install_reason("python3").unwrap(), "Installed automatically on 2024-01-31 18:01 UTC, required by: blender");
+        assert!(db.install_reason("vim").is_none());
+    }
+}
@@ -0,0 +1,210 @@
+/*!
+ * What a transaction would do, read from xbps-install/xbps-remove -n.
+ *
+ * Each line of a dry run is: <pkgver> <action> <arch> <repository> <installed_size> <download_size>
+ * Sizes are in bytes, or '?' if unknown.
+ *
+ * Dry runs only print the new version, so the version being replaced is looked up in the installed packages.
+ */
+use std::fmt::Display;
+
+use mythos_core::{printerror, printwarn};
+
+use crate::{utils::{format_size, split_name_block}, version::PkgVersion, xbps_events::XbpsEvent, QueryResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanAction {
+    Install,
+    Update,
+    Reinstall,
+    Remove,
+    Configure,
+    Download,
+    Hold,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanEntry {
+    pub action: PlanAction,
+    pub pkg_name: String,
+    /// Installed version. None if the package isn't installed.
+    pub old_version: Option<PkgVersion>,
+    pub new_version: PkgVersion,
+    pub installed_size: Option<u64>,
+    pub download_size: Option<u64>,
+}
+/**
+ * Every package a transaction touches, including dependencies and orphans.
+ * errors: Anything that went wrong during the dry run, e.g. unresolved shlibs.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionPlan {
+    pub entries: Vec<PlanEntry>,
+    pub errors: Vec<XbpsEvent>,
+}
+
+impl PlanAction {
+    pub fn parse(action: &str) -> Option<PlanAction> {
+        return match action {
+            "install" => Some(PlanAction::Install),
+            "update" => Some(PlanAction::Update),
+            "reinstall" => Some(PlanAction::Reinstall),
+            "remove" => Some(PlanAction::Remove),
+            "configure" => Some(PlanAction::Configure),
+            "download" => Some(PlanAction::Download),
+            "hold" => Some(PlanAction::Hold),
+            _ => None,
+        };
+    }
+}
+impl PlanEntry {
+    pub fn parse(line: &str) -> Option<PlanEntry> {
+        //! None if line isn't part of the plan.
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 4 {
+            return None;
+        }
+        let action = PlanAction::parse(fields[1])?;
+        let (pkg_name, new_version) = split_name_block(fields[0]);
+        if pkg_name.is_empty() || new_version.is_empty() {
+            return None;
+        }
+        let size = |i: usize| fields.get(i).and_then(|x| x.parse::<u64>().ok());
+        return Some(PlanEntry {
+            action,
+            pkg_name,
+            old_version: None,
+            new_version,
+            installed_size: size(4),
+            download_size: size(5),
+        });
+    }
+    fn version_change(&self) -> String {
+        return match &self.old_version {
+            Some(old) if self.action == PlanAction::Update => format!("{old} -> {}", self.new_version),
+            _ => self.new_version.to_string(),
+        };
+    }
+}
+impl TransactionPlan {
+    pub fn from_events<T>(events: T, installed: &[QueryResult]) -> TransactionPlan where T: Iterator<Item = XbpsEvent> {
+        /*!
+         * Build the plan from the events of a dry run.
+         * Output that isn't part of the plan is dropped, except for errors and warnings.
+         */
+        let mut plan = TransactionPlan::default();
+        for event in events {
+            match event {
+                XbpsEvent::Message(line) => {
+                    if let Some(mut entry) = PlanEntry::parse(&line) {
+                        entry.old_version = installed.iter().find(|p| p.pkg_name == entry.pkg_name).map(|p| p.pkg_version.clone());
+                        plan.entries.push(entry);
+                    }
+                },
+                XbpsEvent::Error(_) | XbpsEvent::Warning(_) => plan.errors.push(event),
+                _ => (),
+            };
+        }
+        return plan;
+    }
+    pub fn download_size(&self) -> u64 {
+        return self.entries.iter().filter_map(|x| x.download_size).sum();
+    }
+    pub fn installed_size(&self) -> u64 {
+        //! Size of everything installed or updated.
+        return self.entries.iter()
+            .filter(|x| matches!(x.action, PlanAction::Install | PlanAction::Update | PlanAction::Reinstall))
+            .filter_map(|x| x.installed_size)
+            .sum();
+    }
+    pub fn freed_size(&self) -> u64 {
+        //! Size of everything removed.
+        return self.entries.iter()
+            .filter(|x| x.action == PlanAction::Remove)
+            .filter_map(|x| x.installed_size)
+            .sum();
+    }
+    pub fn report(&self) {
+        //! Print the problems found by the dry run. They are left for the real transaction to deal with.
+        for event in self.errors.iter() {
+            match event {
+                XbpsEvent::Warning(msg) => printwarn!("{msg}"),
+                _ => printerror!("{event}"),
+            };
+        }
+    }
+    pub fn table(&self) -> String {
+        /*!
+         * Action   Package   Version             Download   Installed
+         * update   python3   3.12.1_1 -> 3.12.2_1  5.6 MB     24.1 MB
+         *
+         * Followed by the totals that aren't 0.
+         */
+        let size = |x: Option<u64>| x.map(format_size).unwrap_or("-".into());
+        let mut rows = vec![["Action".to_string(), "Package".into(), "Version".into(), "Download".into(), "Installed".into()]];
+        rows.extend(self.entries.iter().map(|x| [x.action.to_string(), x.pkg_name.to_owned(), x.version_change(), size(x.download_size), size(x.installed_size)]));
+
+        let widths = (0..5).map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0)).collect::<Vec<usize>>();
+        let mut output = rows.iter()
+            .map(|row| row.iter().zip(widths.iter()).map(|(x, width)| format!("{x:<width$}")).collect::<Vec<String>>().join("  ").trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        output += "\n";
+        let totals = [
+            ("Total download size", self.download_size()),
+            ("Total installed size", self.installed_size()),
+            ("Space freed", self.freed_size()),
+        ];
+        for (name, total) in totals.iter().filter(|(_, total)| *total > 0) {
+            output += &format!("\n{name}: {}", format_size(*total));
+        }
+        return output;
+    }
+}
+impl Display for PlanAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}", match self {
+            PlanAction::Install => "install",
+            PlanAction::Update => "update",
+            PlanAction::Reinstall => "reinstall",
+            PlanAction::Remove => "remove",
+            PlanAction::Configure => "configure",
+            PlanAction::Download => "download",
+            PlanAction::Hold => "hold",
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{plan::*, xbps_events::{parse_event, OutputLine}};
+
+    const DRY_RUN: &str = "blender-4.0.2_1 install x86_64 https://repo-default.voidlinux.org/current 412405760 102760448
+python3-3.12.2_1 update x86_64 https://repo-default.voidlinux.org/current 25270272 5872025
+libfoo-1.0_1 remove x86_64 ? 1048576 ?
+";
+
+    #[test]
+    fn test_plan() {
+        let installed = vec![
+            QueryResult { pkg_name: "python3".into(), pkg_version: "3.12.1_1".into(), ..Default::default() },
+            QueryResult { pkg_name: "libfoo".into(), pkg_version: "1.0_1".into(), ..Default::default() },
+        ];
+        let events = DRY_RUN.lines().map(|x| parse_event(OutputLine::Stdout(x.into())));
+        let plan = TransactionPlan::from_events(events, &installed);
+
+        assert_eq!(plan.entries.len(), 3);
+        assert_eq!(plan.entries[0].action, PlanAction::Install);
+        assert_eq!(plan.entries[0].old_version, None);
+        assert_eq!(plan.entries[1].old_version, Some("3.12.1_1".into()));
+        assert_eq!(plan.entries[2].download_size, None);
+        assert_eq!(plan.download_size(), 102760448 + 5872025);
+        assert_eq!(plan.freed_size(), 1048576);
+
+        let table = plan.table();
+        assert!(table.starts_with("Action   Package  Version               Download  Installed\n"));
+        assert!(table.contains("\nupdate   python3  3.12.1_1 -> 3.12.2_1  5.6 MB    24.1 MB\n"));
+        assert!(table.contains("\nremove   libfoo   1.0_1                 -         1.0 MB\n"));
+        assert!(table.ends_with("\nTotal download size: 103.6 MB\nTotal installed size: 417.4 MB\nSpace freed: 1.0 MB"));
+    }
+}
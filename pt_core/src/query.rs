@@ -1,97 +1,166 @@
-use crate::utils::{parse_xbps_output, read_multiple_index, read_single_index};
-use std::{fs, io::{stdin, stdout, Read, Write}, process::{Command, Stdio}};
+use crate::{backend::PackageBackend, charon::list_charon, scorer::score_results, utils::{read_multiple_index, read_single_index}, version::PkgSpec};
+use std::{collections::HashMap, io::{stdin, stdout, Read, Write}};
 
-use mythos_core::{cli::{self, get_cli_input}, dirs, fatalmsg, printerror};
-use toml::Value;
+use mythos_core::{cli::{self, get_cli_input}, printerror};
 
 use termion::{self, clear};
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
-use crate::{Query, QueryError, QueryResult};
+use crate::{Query, QueryError, QueryResult, SearchOpts};
 
-// Minimum score package must get using fuzzy find to be included in results.
-const THRESHOLD: f32 = 0.3;
 const SMALL_LIST_SIZE: usize = 50;
 
 impl Query{
-    pub fn query(search_term: &str) -> Result<Query, QueryError> {
+    pub fn query(backend: &dyn PackageBackend, search_term: &str, opts: &SearchOpts) -> Result<Query, QueryError> {
         /*!
             * Find packages that match search_term.
-            * Tries to find package using xrs.
-            * Then checks to see if program was installed using charon.
+            * Searches the backend's repositories and the programs installed using charon.
             * Finally, checks list of tertiary package managers.
+            * If one of them has it, returns QueryError::TertiaryList.
          */
-        if let Some(query) = Query::query_xbps(search_term) {
-            return Ok(query);
-        };
-        if let Some(query) = Query::query_charon(search_term) {
-            return Ok(Query::from(query));
-        };
-        return Err(QueryError::NotFound(format!("Package not found: '{search_term}'")));
+        return Query::query_many(backend, &[search_term], opts).remove(0);
     }
+    pub fn query_many(backend: &dyn PackageBackend, search_terms: &[&str], opts: &SearchOpts) -> Vec<Result<Query, QueryError>> {
+        /*!
+            * Same as query(), but the repository and charon listings are only fetched once for all terms.
+            * Returns one result per search term, in the same order as search_terms.
+         */
+        let mut listings = match backend.search_many(search_terms, opts) {
+            Ok(listings) => listings,
+            Err(msg) => {
+                printerror!("Error running query for {}: {msg}", search_terms.join(", "));
+                vec![Vec::new(); search_terms.len()]
+            }
+        };
 
-    pub fn query_xbps(search_term: &str) -> Option<Query> {
-        let raw_results = Command::new("xrs")
-            .stderr(Stdio::piped())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            // Using search_term here works, unless no pkgs are found.
-            // So if the user does cocytus 'bledner' instead of 'blender', it will return nothing.
-            // Doing it this way would allow the query to find what the user likely meant.
-            .arg("")
-            .output()
-            .expect(&fatalmsg!("Error running query for {search_term}"))
-            .stdout;
-
-
-        let (mut results, longest_name) = parse_xbps_output(raw_results, search_term, THRESHOLD);
+        let charon = list_charon();
 
-        if results.len() == 0 {
-            return None;
+        let mut output = search_terms.iter().zip(listings.drain(..)).map(|(search_term, mut results)| {
+            // Charon programs are scored the same way as xbps packages, and ranked alongside them.
+            results.extend(score_results(&charon, search_term, opts));
+            if let Some(query) = Query::from_search_results(search_term, results) {
+                return Ok(query);
+            };
+            return Err(QueryError::NotFound(format!("Package not found: '{search_term}'")));
+        }).collect::<Vec<Result<Query, QueryError>>>();
+
+        // Tertiary package managers are only searched for terms that weren't found.
+        let missing = search_terms.iter().zip(output.iter())
+            .filter(|(_, query)| query.is_err())
+            .map(|(search_term, _)| *search_term)
+            .collect::<Vec<&str>>();
+        if missing.len() == 0 || opts.exact {
+            return output;
         }
-
-        results.sort_by(|a, b| b.score.cmp(&a.score));
-
-        if results[0].score >= 100 {
-            results = vec![results.remove(0)];
+        let mut tertiary = missing.iter().zip(backend.search_tertiary(&missing));
+        for query in output.iter_mut().filter(|query| query.is_err()) {
+            let (search_term, pkgs) = match tertiary.next() {
+                Some(res) => res,
+                None => break,
+            };
+            if pkgs.len() > 0 {
+                let ids = pkgs.iter().map(|p| format!("{} as {}", p.manager, p.pkg_id)).collect::<Vec<String>>();
+                *query = Err(QueryError::TertiaryList(format!("'{search_term}' was not found in xbps or charon. Available via {}", ids.join(", ")), pkgs));
+            }
         }
+        return output;
+    }
 
-        return Some(Query { results, longest_name, pkg_name: search_term.into() });
+    pub fn query_exact(backend: &dyn PackageBackend, search_terms: &[&str], opts: &SearchOpts) -> Result<Vec<QueryResult>, Vec<String>> {
+        /*!
+            * Find the package named by each search term, without fuzzy matching.
+            * Returns every search term that doesn't name a package verbatim as the error.
+         */
+        let opts = SearchOpts { exact: true, ..opts.clone() };
+        let mut output: Vec<QueryResult> = Vec::new();
+        let mut missing: Vec<String> = Vec::new();
+
+        for (search_term, query) in search_terms.iter().zip(Query::query_many(backend, search_terms, &opts)) {
+            match query.ok().and_then(|q| q.results.into_iter().find(|p| p.pkg_name == *search_term)) {
+                Some(pkg) => output.push(pkg),
+                None => missing.push(search_term.to_string()),
+            }
+        }
+        if missing.len() > 0 {
+            return Err(missing);
+        }
+        return Ok(output);
     }
-    pub fn query_charon(search_term: &str) -> Option<QueryResult> {
-        //! Check if search term is contained inside of index.charon.
-        let path = dirs::get_path(dirs::MythosDir::Data, "charon/index.charon")?;
-        let res = match fs::read_to_string(path) {
-            Ok(res) => res,
+    pub fn query_specs(backend: &dyn PackageBackend, specs: &[PkgSpec], opts: &SearchOpts) -> Result<Vec<QueryResult>, Vec<String>> {
+        /*!
+            * Find an xbps package satisfying each spec's version constraints.
+            * Both the available and the installed versions are checked.
+            * Picks the newest version that satisfies the spec, preferring the installed one.
+            * Returns a message for every spec that can't be satisfied as the error.
+         */
+        let opts = SearchOpts { exact: true, ..opts.clone() };
+        let names = specs.iter().map(|spec| spec.pkg_name.as_str()).collect::<Vec<&str>>();
+        let installed = match backend.list_installed() {
+            Ok(installed) => installed,
             Err(msg) => {
-                printerror!("Could not load charon file: {msg}");
-                return None;
+                printerror!("Could not list installed packages: {msg}");
+                Vec::new()
             }
         };
+        let mut output: Vec<QueryResult> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
 
-        let table: Value = match toml::from_str(&res) {
-            Ok(table) => table,
+        for (spec, query) in specs.iter().zip(Query::query_many(backend, &names, &opts)) {
+            if !spec.is_valid() {
+                errors.push(format!("Invalid package spec: '{spec}'"));
+                continue;
+            }
+            let mut candidates = query.map(|q| q.results).unwrap_or_default();
+            candidates.extend(installed.iter().cloned());
+            candidates.retain(|p| p.pkg_name == spec.pkg_name && p.origin.is_xbps());
+
+            let best = candidates.iter()
+                .filter(|p| spec.matches(&p.pkg_version))
+                .max_by(|a, b| a.pkg_version.cmp(&b.pkg_version).then(a.is_installed.cmp(&b.is_installed)));
+            match best {
+                Some(pkg) => output.push(QueryResult { pkg_spec: Some(spec.clone()), ..pkg.clone() }),
+                None if candidates.len() == 0 => errors.push(format!("Package not found: '{}'", spec.pkg_name)),
+                None => {
+                    let mut versions = candidates.iter()
+                        .map(|p| format!("{}{}", p.pkg_version, if p.is_installed { " (installed)" } else { "" }))
+                        .collect::<Vec<String>>();
+                    versions.dedup();
+                    errors.push(format!("No version of '{}' satisfies '{spec}'. Found: {}", spec.pkg_name, versions.join(", ")));
+                },
+            }
+        }
+        if errors.len() > 0 {
+            return Err(errors);
+        }
+        return Ok(output);
+    }
+    pub fn query_xbps(backend: &dyn PackageBackend, search_term: &str, opts: &SearchOpts) -> Option<Query> {
+        let results = match backend.search(search_term, opts) {
+            Ok(results) => results,
             Err(msg) => {
-                printerror!("Could not parse charon file: {msg}");
+                printerror!("Error running query for {search_term}: {msg}");
                 return None;
             }
         };
+        return Query::from_search_results(search_term, results);
+    }
+    fn from_search_results(search_term: &str, mut results: Vec<QueryResult>) -> Option<Query> {
+        /*!
+         * Sort results by score. If there are exact matches, only keep them.
+         * Exact matches from different sources (e.g. xbps and charon) are all kept.
+         */
+        if results.len() == 0 {
+            return None;
+        }
 
-        // Get table value
-        if let Value::Table(table) = table {
-            if let Some(Value::Table(val)) = &table.get(search_term) {
-                return Some(QueryResult {
-                    is_installed: true,
-                    pkg_name: search_term.into(),
-                    pkg_version: if let Some(Value::String(val)) = val.get("version") { val.to_owned() } else { "".into() },
-                    pkg_description: if let Some(Value::String(val)) = val.get("description") { val.to_owned() } else { "".into() },
-                    score: 100,
-                });
-            }
+        results.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.pkg_name.cmp(&b.pkg_name)));
+
+        if results[0].score >= 100 {
+            results.retain(|x| x.score >= 100);
         }
 
-        return None;
+        return Some(Query { pkg_name: search_term.into(), ..Query::from(results) });
     }
     pub fn select_from_results(&self) -> Option<Query> {
         /*!
@@ -118,7 +187,7 @@ impl Query{
                 };
 
                 return match results {
-                    Some(res) => Some(Query { results: res.0, longest_name: res.1, pkg_name: format!("{} (Modified)", self.pkg_name) }),
+                    Some(res) => Some(Query { pkg_name: format!("{} (Modified)", self.pkg_name), ..Query::from(res.0) }),
                     None => {
                         eprintln!("Please enter an option above");
                         continue;
@@ -130,9 +199,8 @@ impl Query{
         // List is too large to display, use less-like format.
         let res = self.show_long_list("0 to Remove package or select from the options above: ")?;
         return Some(Query { 
-            results: res.0, 
-            longest_name: res.1, 
-            pkg_name: format!("{} (Modified)", self.pkg_name) 
+            pkg_name: format!("{} (Modified)", self.pkg_name),
+            ..Query::from(res.0)
         });
     }
     pub fn get_short_list(&self) -> String {
@@ -172,13 +240,14 @@ impl Query{
 
         let mut row_counter = 1;
         let longest_name = self.longest_name;
+        let names = list_names(&self.results);
 
         let mut i = start_index as isize - 1;
-        for res in self.results.iter().skip(start_index) {
+        for name in names.iter().skip(start_index) {
             i += 1;
             if i == end_index as isize { break; }
             // Current index of number + padding zeros + '.' + name + ' '
-            output += &format!("{id:0$}. {name: <longest_name$} ", num_digits, id = i + 1, name = res.pkg_name); 
+            output += &format!("{id:0$}. {name: <longest_name$} ", num_digits, id = i + 1); 
             // Loop down to next row
             if row_counter % columns == 0 {
                 output += "\n\r";
@@ -321,6 +390,13 @@ impl Query{
     pub fn get_pkg_names<'a>(&'a self) -> Vec<&'a str> {
         return self.results.iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>();
     }
+    pub fn get_pkg_specs(&self) -> Vec<String> {
+        //! Same as get_pkg_names(), but packages picked by a version constraint (foo>=1.2) keep it.
+        return self.results.iter().map(|p| match &p.pkg_spec {
+            Some(spec) => spec.to_string(),
+            None => p.pkg_name.to_owned(),
+        }).collect();
+    }
 
     pub fn len(&self) -> usize {
         return self.results.len();
@@ -332,29 +408,27 @@ impl Query{
         return Some(&self.results[index]);
     }
 }
+fn list_names(results: &[QueryResult]) -> Vec<String> {
+    //! Names shown in lists of results. xbps packages sharing a name are told apart by their repository.
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for res in results.iter().filter(|x| x.origin.is_xbps()) {
+        *counts.entry(&res.pkg_name).or_default() += 1;
+    }
+    return results.iter().map(|res| {
+        if res.origin.is_xbps() && counts[res.pkg_name.as_str()] > 1 {
+            return format!("{} ({})", res.list_name(), res.origin);
+        }
+        return res.list_name();
+    }).collect();
+}
 impl From<QueryResult> for Query {
     fn from(value: QueryResult) -> Self {
-        let name = &value.pkg_name;
-        return Query {
-            pkg_name: name.to_owned(),
-            results: vec![value.clone()],
-            longest_name: name.len(),
-        };
+        return Query { pkg_name: value.pkg_name.to_owned(), ..Query::from(vec![value]) };
     }
 }
 impl From<Vec<QueryResult>> for Query {
     fn from(value: Vec<QueryResult>) -> Self {
-        let longest_name: usize = if value.len() > 0 {
-            value.iter().reduce(|acc, x| { 
-                if x.pkg_name.len() > acc.pkg_name.len() { 
-                    x 
-                } else { 
-                    acc 
-                }
-            }).unwrap().pkg_name.len()
-        } else {
-            0
-        };
+        let longest_name = list_names(&value).iter().map(|x| x.len()).max().unwrap_or(0);
 
         return Query {
             pkg_name: "".into(),
@@ -387,40 +461,131 @@ mod tests {
         * - Select from multiple queries.
      */
     use crate::*;
+    use crate::backend::{fake_pkg, FakeBackend};
+    use super::list_names;
 
+    fn repo() -> FakeBackend {
+        return FakeBackend::new(vec![fake_pkg("blender", false), fake_pkg("blender-devel", false), fake_pkg("blend2d", false), fake_pkg("vim", true)]);
+    }
     #[test]
     fn test_xbps_query() {
-        let res = Query::query_xbps("blende").unwrap();
+        let res = Query::query_xbps(&repo(), "blende", &SearchOpts::default()).unwrap();
         assert_eq!(res.results[0].pkg_name, "blender");
     }
     #[test]
-    fn test_charon_query() {
-        let res = Query::query_charon("charon");
-        assert_eq!("charon", res.unwrap().pkg_name);
-        let res2 = Query::query_charon("hello");
-        assert!(matches!(res2, None));
+    fn test_exact_match() {
+        let res = Query::query_xbps(&repo(), "blender", &SearchOpts::default()).unwrap();
+        assert_eq!(res.results[0].pkg_name, "blender");
+        assert_eq!(res.results.len(), 1);
     }
     #[test]
-    fn test_exact_match() {
-        let res = Query::query_xbps("blender").unwrap();
+    fn test_fake_backend_query() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), fake_pkg("blend2d", false), fake_pkg("vim", true)]);
+        let res = Query::query_xbps(&backend, "blende", &SearchOpts::default()).unwrap();
         assert_eq!(res.results[0].pkg_name, "blender");
+        assert!(!res.get_pkg_names().contains(&"vim"));
+
+        let res = Query::query_xbps(&backend, "blender", &SearchOpts::default()).unwrap();
         assert_eq!(res.results.len(), 1);
     }
+    #[test]
+    fn test_query_many() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), fake_pkg("vim", true)]);
+        let res = Query::query_many(&backend, &["vim", "blende", "zzzzzz"], &SearchOpts::default());
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].as_ref().unwrap().get_pkg_names(), vec!["vim"]);
+        assert_eq!(res[1].as_ref().unwrap().get_pkg_names(), vec!["blender"]);
+        assert!(matches!(res[2], Err(QueryError::NotFound(_))));
+    }
+    #[test]
+    fn test_tertiary_fallback() {
+        let flatpak = TertiaryPkg { manager: "flatpak".into(), pkg_id: "org.blender.Blender".into(), pkg_name: "Blender".into(), ..Default::default() };
+        let mut backend = FakeBackend::new(vec![fake_pkg("vim", true)]);
+        backend.tertiary = vec![flatpak];
+        let res = Query::query_many(&backend, &["vim", "blender"], &SearchOpts::default());
+        assert!(res[0].is_ok());
+        assert!(matches!(&res[1], Err(QueryError::TertiaryList(_, pkgs)) if pkgs[0].pkg_id == "org.blender.Blender"));
+
+        // Exact mode doesn't look any further than xbps and charon.
+        let res = Query::query_many(&backend, &["blender"], &SearchOpts { exact: true, ..Default::default() });
+        assert!(matches!(res[0], Err(QueryError::NotFound(_))));
+    }
+    #[test]
+    fn test_duplicate_names_are_kept() {
+        let backend = FakeBackend::new(vec![
+            QueryResult { origin: PkgOrigin::Xbps("https://repo-default.voidlinux.org/current".into()), ..fake_pkg("vim", false) },
+            QueryResult { origin: PkgOrigin::Xbps("https://example.org/repo".into()), ..fake_pkg("vim", false) },
+            fake_pkg("vim-huge", false),
+        ]);
+        let res = Query::query(&backend, "vim", &SearchOpts::default()).unwrap();
+        assert_eq!(res.get_pkg_names(), vec!["vim", "vim"]);
+        assert_eq!(list_names(&res.results), vec!["vim (https://repo-default.voidlinux.org/current)", "vim (https://example.org/repo)"]);
+    }
+    #[test]
+    fn test_query_pattern() {
+        let backend = FakeBackend::new(vec![fake_pkg("font-util", false), fake_pkg("font-misc-misc", true), fake_pkg("fontconfig", true)]);
+        let res = Query::query(&backend, "font-*", &SearchOpts::default()).unwrap();
+        assert_eq!(res.get_pkg_names(), vec!["font-misc-misc", "font-util"]);
+    }
+    #[test]
+    fn test_query_exact() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), fake_pkg("vim", true)]);
+        let res = Query::query_exact(&backend, &["vim", "blender"], &SearchOpts::default()).unwrap();
+        assert_eq!(res.iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>(), vec!["vim", "blender"]);
+
+        let missing = Query::query_exact(&backend, &["vim", "blende", "vi"], &SearchOpts::default()).unwrap_err();
+        assert_eq!(missing, vec!["blende", "vi"]);
+    }
+    #[test]
+    fn test_virtual_pkgs() {
+        let provider = |name: &str| QueryResult { pkg_provides: vec!["awk-0_1".into()], ..fake_pkg(name, false) };
+        let backend = FakeBackend::new(vec![provider("gawk"), provider("mawk"), fake_pkg("awkward", false)]);
+        let res = Query::query(&backend, "awk", &SearchOpts::default()).unwrap();
+        assert_eq!(list_names(&res.results), vec!["gawk (provides awk)", "mawk (provides awk)"]);
+        assert!(res.results.iter().all(|p| p.virtual_name.as_deref() == Some("awk")));
+        // Single term searches find providers too.
+        let res = Query::query_xbps(&backend, "awk", &SearchOpts::default()).unwrap();
+        assert_eq!(res.get_pkg_names(), vec!["gawk", "mawk"]);
+
+        // Searching for the provider itself doesn't mark it.
+        let res = Query::query(&backend, "gawk", &SearchOpts::default()).unwrap();
+        assert_eq!(res.results[0].virtual_name, None);
+    }
+    #[test]
+    fn test_query_specs() {
+        let installed = QueryResult { pkg_version: "1.2_1".into(), ..fake_pkg("vim", true) };
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), installed]);
+        let specs = ["blender>=1.0", "vim<2"].map(|x| PkgSpec::parse(x).unwrap());
+        let res = Query::query_specs(&backend, &specs, &SearchOpts::default()).unwrap();
+        assert_eq!(res[0].pkg_version, "1.0_1");
+        assert_eq!(res[1].pkg_version, "1.2_1");
+        assert_eq!(res[1].pkg_spec, Some(specs[1].clone()));
+        let res = Query::from([res, vec![fake_pkg("vim-huge", false)]].concat());
+        assert_eq!(res.get_pkg_specs(), vec!["blender>=1.0", "vim<2", "vim-huge"]);
+
+        let specs = ["blender>=2.0", "vim-1.0_1", "blende>1"].map(|x| PkgSpec::parse(x).unwrap());
+        let errors = Query::query_specs(&backend, &specs, &SearchOpts::default()).unwrap_err();
+        assert_eq!(errors, vec![
+            "No version of 'blender' satisfies 'blender>=2.0'. Found: 1.0_1",
+            "No version of 'vim' satisfies 'vim-1.0_1'. Found: 1.2_1 (installed)",
+            "Package not found: 'blende'",
+        ]);
+    }
     // #[test]
     fn test_selection() {
-        let res = Query::query_xbps("blen").unwrap();
+        let res = Query::query_xbps(&repo(), "blen", &SearchOpts::default()).unwrap();
         let output = res.select_from_results();
         println!("{:?}", output);
     }
     // #[test]
     fn test_short_display_list() {
-        let res = Query::query_xbps("bl").unwrap();
+        let res = Query::query_xbps(&repo(), "bl", &SearchOpts::default()).unwrap();
         let output = res.get_short_list();
         println!("{output}");
     }
     // #[test]
     fn test_long_display_list() {
-        let res = Query::query_xbps("b").unwrap();
+        let res = Query::query_xbps(&repo(), "b", &SearchOpts::default()).unwrap();
         let output = res.show_long_list("...");
         assert!(true);
     }
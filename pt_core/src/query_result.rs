@@ -1,19 +1,19 @@
-use duct::cmd;
-use mythos_core::printerror;
-use crate::{QueryResult, parse_output};
+use std::fmt::Display;
 
-// Runs xq {pkg} | head -n HEAD_LINE_COUNT
-const HEAD_LINE_COUNT: &str = "12";
+use mythos_core::printerror;
+use crate::{backend::PackageBackend, utils::format_size, PkgOrigin, QueryResult};
 
 impl QueryResult {
-    pub fn long_display(&self) -> String {
+    pub fn long_display(&self, backend: &dyn PackageBackend) -> String {
         /*!
-            * Run xq {pkg} | head -n HEAD_LINE_COUNT
+            * Ask backend for detailed info about this package.
+            * Backend only knows about xbps packages, for the rest use display().
         */
-        let cmd = cmd!("xq", &self.pkg_name).pipe(cmd!("head", "-n", HEAD_LINE_COUNT));
-
-        let output = match cmd.run() {
-            Ok(output) => parse_output(output.stdout),
+        if !self.origin.is_xbps() {
+            return self.display();
+        }
+        let output = match backend.info(&self.pkg_name) {
+            Ok(info) => info.to_string(),
             Err(msg) => {
                 printerror!("{msg}");
                 return "".into();
@@ -27,19 +27,67 @@ impl QueryResult {
             * {PKG_NAME} [{*|-}]
             * Version: {version}
             * Description: {description}
+            * Source: {origin}      (If known)
+            * Size: {size}          (If known)
+            * Homepage: {homepage}  (If known)
         */
-        let output = format!("{} [{}]\nVersion: {}\nDescription: {}",
+        let mut output = format!("{} [{}]\nVersion: {}\nDescription: {}",
             self.pkg_name,
             if self.is_installed { "*" } else { "-" },
             self.pkg_version,
             self.pkg_description);
 
+        if self.origin != PkgOrigin::default() {
+            output += &format!("\nSource: {}", self.origin);
+        }
+        if self.pkg_size > 0 {
+            output += &format!("\nSize: {}", format_size(self.pkg_size));
+        }
+        if !self.pkg_homepage.is_empty() {
+            output += &format!("\nHomepage: {}", self.pkg_homepage);
+        }
+
         return output;
     }
+    pub fn list_name(&self) -> String {
+        /*!
+         * Name shown in lists of results. Tagged with its origin, unless it is from xbps.
+         * Providers of a virtual package are tagged with it instead.
+         */
+        if let Some(virtual_name) = &self.virtual_name {
+            return format!("{} (provides {virtual_name})", self.pkg_name);
+        }
+        if self.origin.is_xbps() {
+            return self.pkg_name.to_owned();
+        }
+        return format!("{} ({})", self.pkg_name, self.origin);
+    }
+}
+
+impl PkgOrigin {
+    pub fn is_xbps(&self) -> bool {
+        return matches!(self, PkgOrigin::Xbps(_));
+    }
+}
+impl Default for PkgOrigin {
+    fn default() -> Self {
+        return PkgOrigin::Xbps("".into());
+    }
+}
+impl Display for PkgOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            PkgOrigin::Xbps(repo) if repo.is_empty() => write!(f, "xbps"),
+            PkgOrigin::Xbps(repo) => write!(f, "{repo}"),
+            PkgOrigin::Charon => write!(f, "charon"),
+            PkgOrigin::External(manager) => write!(f, "{manager}"),
+        };
+    }
 }
 
 impl PartialEq for QueryResult {
     fn eq(&self, other: &Self) -> bool {
-        return self.pkg_name == other.pkg_name;
+        //! The same package name can come from multiple sources.
+        return self.pkg_name == other.pkg_name && self.origin == other.origin;
     }
 }
@@ -0,0 +1,263 @@
+/*!
+ * Reads the repository indexes xbps caches under /var/db/xbps.
+ *
+ * Each repository gets a directory (e.g. https___repo-default_voidlinux_org_current),
+ * containing one `<arch>-repodata` file per architecture.
+ * A repodata file is a zstd compressed tar archive, whose index.plist maps pkg names to their properties.
+ *
+ * Directory names are repository URLs with '.', ':' and '/' replaced by '_', which can't be reversed.
+ * The URL is found by escaping each repository listed in the xbps.d config files instead.
+ */
+use std::{collections::HashSet, fs::{self, File}, io::{self, Read}, path::{Path, PathBuf}};
+
+use mythos_core::printwarn;
+
+use crate::{utils::{plist_string, plist_strings, split_name_block}, PkgOrigin, QueryResult};
+
+pub const XBPS_DB_DIR: &str = "/var/db/xbps";
+/// User config overrides system config, see xbps.d(5).
+pub const XBPS_CONF_DIRS: [&str; 2] = ["/etc/xbps.d", "/usr/share/xbps.d"];
+const REPODATA_SUFFIX: &str = "-repodata";
+const INDEX_FILE: &str = "index.plist";
+
+pub fn find_repodata_files(db_dir: &Path) -> Vec<PathBuf> {
+    //! Every <db_dir>/<repo>/<arch>-repodata file, sorted so results are stable across runs.
+    let mut output: Vec<PathBuf> = Vec::new();
+    let repos = match fs::read_dir(db_dir) {
+        Ok(repos) => repos,
+        Err(_) => return output,
+    };
+
+    for repo in repos.flatten() {
+        let files = match fs::read_dir(repo.path()) {
+            Ok(files) => files,
+            Err(_) => continue,
+        };
+        for file in files.flatten() {
+            if file.file_name().to_string_lossy().ends_with(REPODATA_SUFFIX) {
+                output.push(file.path());
+            }
+        }
+    }
+    output.sort();
+    return output;
+}
+
+pub fn read_all_repodata(db_dir: &Path) -> io::Result<Vec<QueryResult>> {
+    /*!
+     * Read every repodata file inside of db_dir.
+     * Each package's origin is the URL of its repository.
+     * If a package is in multiple repositories, it is listed once for each of them.
+     * Files that can't be read (e.g. corrupt or partially synced) are skipped with a warning.
+     * Returns NotFound if xbps has not synced any repositories yet, or the last error if no file could be read.
+     */
+    let files = find_repodata_files(db_dir);
+    if files.len() == 0 {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No repodata found in {}", db_dir.display())));
+    }
+    let repos = configured_repos();
+
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut output: Vec<QueryResult> = Vec::new();
+    let mut last_error: Option<io::Error> = None;
+    let mut read_count = 0;
+    for file in files {
+        let repo_dir = file.parent().and_then(|x| x.file_name()).map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        let repo = repo_url(&repo_dir, &repos);
+        let pkgs = match read_repodata(&file) {
+            Ok(pkgs) => pkgs,
+            Err(msg) => {
+                printwarn!("Skipping {}: {msg}", file.display());
+                last_error = Some(msg);
+                continue;
+            }
+        };
+        read_count += 1;
+        for pkg in pkgs {
+            // Same repository can have repodata for more than one arch.
+            if seen.insert((pkg.pkg_name.clone(), repo.clone())) {
+                output.push(QueryResult { origin: PkgOrigin::Xbps(repo.clone()), ..pkg });
+            }
+        }
+    }
+    if let (0, Some(err)) = (read_count, last_error) {
+        return Err(err);
+    }
+    return Ok(output);
+}
+
+pub fn configured_repos() -> Vec<String> {
+    //! Every repository=<url> line in the xbps.d config files.
+    let mut output: Vec<String> = Vec::new();
+    for dir in XBPS_CONF_DIRS {
+        let mut files = match fs::read_dir(dir) {
+            Ok(files) => files.flatten().map(|x| x.path()).collect::<Vec<PathBuf>>(),
+            Err(_) => continue,
+        };
+        files.sort();
+        for file in files.iter().filter(|x| x.extension().is_some_and(|ext| ext == "conf")) {
+            let contents = fs::read_to_string(file).unwrap_or_default();
+            output.extend(parse_repo_lines(&contents));
+        }
+    }
+    return output;
+}
+pub fn parse_repo_lines(contents: &str) -> Vec<String> {
+    return contents.lines()
+        .filter_map(|line| line.trim().strip_prefix("repository"))
+        .filter_map(|line| line.trim_start().strip_prefix('='))
+        .map(|url| url.trim().to_string())
+        .collect();
+}
+pub fn escape_repo_url(url: &str) -> String {
+    //! Same as xbps: https://repo-default.voidlinux.org/current -> https___repo-default_voidlinux_org_current
+    return url.trim_end_matches('/').replace(['.', ':', '/'], "_");
+}
+pub fn repo_url(repo_dir: &str, repos: &[String]) -> String {
+    //! URL of the repository cached in repo_dir. Falls back to repo_dir if no configured repository matches.
+    return repos.iter()
+        .find(|url| escape_repo_url(url) == repo_dir)
+        .cloned()
+        .unwrap_or(repo_dir.to_string());
+}
+
+pub fn read_repodata(path: &Path) -> io::Result<Vec<QueryResult>> {
+    //! Decompress a single repodata archive and parse its index.plist.
+    let decoder = zstd::Decoder::new(File::open(path)?)?;
+    let mut archive = tar::Archive::new(decoder);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy() != INDEX_FILE {
+            continue;
+        }
+        let mut index: Vec<u8> = Vec::new();
+        entry.read_to_end(&mut index)?;
+        return parse_index_plist(&index);
+    }
+    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} does not contain {INDEX_FILE}", path.display())));
+}
+
+pub fn parse_index_plist(index: &[u8]) -> io::Result<Vec<QueryResult>> {
+    /*!
+     * index.plist -> <dict> pkg_name -> <dict> properties
+     * Properties used: pkgver, short_desc, installed_size, run_depends, homepage, provides, shlib-provides.
+     */
+    let value = plist::Value::from_reader_xml(index)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let dict = match value.as_dictionary() {
+        Some(dict) => dict,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{INDEX_FILE} is not a dictionary"))),
+    };
+
+    let mut output: Vec<QueryResult> = Vec::new();
+    for (name, props) in dict.iter() {
+        let props = match props.as_dictionary() {
+            Some(props) => props,
+            None => continue,
+        };
+        let (_, pkg_version) = split_name_block(&plist_string(props, "pkgver"));
+
+        output.push(QueryResult {
+            pkg_name: name.to_owned(),
+            pkg_version,
+            pkg_description: plist_string(props, "short_desc"),
+            pkg_size: props.get("installed_size").and_then(|x| x.as_unsigned_integer()).unwrap_or(0),
+            pkg_depends: plist_strings(props, "run_depends"),
+            pkg_homepage: plist_string(props, "homepage"),
+            pkg_provides: plist_strings(props, "provides"),
+            pkg_shlib_provides: plist_strings(props, "shlib-provides"),
+            ..Default::default()
+        });
+    }
+    return Ok(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repodata::*;
+
+    const INDEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple Computer//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>blender</key>
+	<dict>
+		<key>homepage</key>
+		<string>https://www.blender.org</string>
+		<key>installed_size</key>
+		<integer>412405760</integer>
+		<key>pkgver</key>
+		<string>blender-4.0.2_1</string>
+		<key>run_depends</key>
+		<array>
+			<string>glibc&gt;=2.36_1</string>
+			<string>python3&gt;=3.12_1</string>
+		</array>
+		<key>short_desc</key>
+		<string>3D graphics creation suite</string>
+	</dict>
+	<key>gawk</key>
+	<dict>
+		<key>pkgver</key>
+		<string>gawk-5.3.0_1</string>
+		<key>provides</key>
+		<array>
+			<string>awk-0_1</string>
+		</array>
+		<key>shlib-provides</key>
+		<array>
+			<string>libgawk.so.5</string>
+		</array>
+	</dict>
+	<key>font-misc-misc</key>
+	<dict>
+		<key>pkgver</key>
+		<string>font-misc-misc-1.1.3_1</string>
+	</dict>
+</dict>
+</plist>"#;
+
+    #[test]
+    fn test_parse_index_plist() {
+        let pkgs = parse_index_plist(INDEX.as_bytes()).unwrap();
+        assert_eq!(pkgs.len(), 3);
+        assert_eq!(pkgs[0].pkg_name, "blender");
+        assert_eq!(pkgs[0].pkg_size, 412405760);
+        assert_eq!(pkgs[0].pkg_depends, vec!["glibc>=2.36_1", "python3>=3.12_1"]);
+        assert_eq!(pkgs[0].pkg_homepage, "https://www.blender.org");
+        assert_eq!(pkgs[0].pkg_description, "3D graphics creation suite");
+        assert_eq!(pkgs[1].pkg_name, "gawk");
+        assert_eq!(pkgs[1].pkg_provides, vec!["awk-0_1"]);
+        assert_eq!(pkgs[1].pkg_shlib_provides, vec!["libgawk.so.5"]);
+        assert_eq!(pkgs[2].pkg_name, "font-misc-misc");
+        assert_eq!(pkgs[2].pkg_version, "1.1.3_1");
+    }
+    #[test]
+    fn test_skip_bad_repodata() {
+        let db_dir = std::env::temp_dir().join("pt_core_test_skip_bad_repodata");
+        let _ = fs::remove_dir_all(&db_dir);
+        fs::create_dir_all(db_dir.join("repo_a")).unwrap();
+        fs::create_dir_all(db_dir.join("repo_b")).unwrap();
+        fs::write(db_dir.join("repo_a/x86_64-repodata"), "partially synced").unwrap();
+        assert!(read_all_repodata(&db_dir).is_err());
+
+        let mut archive = tar::Builder::new(zstd::Encoder::new(File::create(db_dir.join("repo_b/x86_64-repodata")).unwrap(), 0).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(INDEX.len() as u64);
+        header.set_cksum();
+        archive.append_data(&mut header, INDEX_FILE, INDEX.as_bytes()).unwrap();
+        archive.into_inner().unwrap().finish().unwrap();
+
+        let pkgs = read_all_repodata(&db_dir).unwrap();
+        assert_eq!(pkgs.len(), 3);
+        let _ = fs::remove_dir_all(&db_dir);
+    }
+    #[test]
+    fn test_repo_url() {
+        let repos = parse_repo_lines("# Mirror\nrepository=https://repo-default.voidlinux.org/current\nrepository = https://repo-default.voidlinux.org/current/nonfree\n");
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repo_url("https___repo-default_voidlinux_org_current_nonfree", &repos), "https://repo-default.voidlinux.org/current/nonfree");
+        assert_eq!(repo_url("https___example_org_repo", &repos), "https___example_org_repo");
+    }
+}
@@ -0,0 +1,316 @@
+/*!
+ * Strategies used to score packages against a search term.
+ *
+ * Scorers are built once per search term (e.g. regexes are compiled up front),
+ * then used to score every package in the listing.
+ * Strict strategies (prefix, substring, regex) score 0 if there is no match, and at least 0.5 if
+ * there is one. Longer matches score higher.
+ */
+use std::{fmt::Display, str::FromStr};
+
+use mythos_core::printwarn;
+use regex::Regex;
+use rust_fuzzy_search::fuzzy_compare;
+
+use crate::{pattern::PatternKind, utils::description_words, QueryResult, SearchOpts};
+
+pub trait Scorer {
+    /// Similarity of text to the search term, between 0 and 1.
+    fn score(&self, text: &str) -> f32;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScorerKind {
+    /// Trigram similarity, see rust_fuzzy_search.
+    #[default]
+    Fuzzy,
+    Prefix,
+    Substring,
+    /// Damerau-Levenshtein edit distance.
+    Levenshtein,
+    Regex,
+}
+
+impl ScorerKind {
+    pub fn build(&self, search_term: &str) -> Box<dyn Scorer> {
+        return match self {
+            ScorerKind::Fuzzy => Box::new(FuzzyScorer(search_term.into())),
+            ScorerKind::Prefix => Box::new(PrefixScorer(search_term.into())),
+            ScorerKind::Substring => Box::new(SubstringScorer(search_term.into())),
+            ScorerKind::Levenshtein => Box::new(LevenshteinScorer(search_term.chars().collect())),
+            ScorerKind::Regex => Box::new(RegexScorer::new(search_term)),
+        };
+    }
+}
+impl FromStr for ScorerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "fuzzy" | "trigram" => Ok(ScorerKind::Fuzzy),
+            "prefix" => Ok(ScorerKind::Prefix),
+            "substring" => Ok(ScorerKind::Substring),
+            "levenshtein" | "damerau" => Ok(ScorerKind::Levenshtein),
+            "regex" => Ok(ScorerKind::Regex),
+            _ => Err(format!("Unknown scorer: '{s}'. Expected one of fuzzy, prefix, substring, levenshtein, regex")),
+        };
+    }
+}
+impl Display for ScorerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ScorerKind::Fuzzy => "fuzzy",
+            ScorerKind::Prefix => "prefix",
+            ScorerKind::Substring => "substring",
+            ScorerKind::Levenshtein => "levenshtein",
+            ScorerKind::Regex => "regex",
+        };
+        return write!(f, "{name}");
+    }
+}
+
+fn strict_match_score(match_len: usize, text_len: usize) -> f32 {
+    //! Matches score [0.5, 1], depending on how much of text was matched.
+    if text_len == 0 {
+        return 1.0;
+    }
+    return 0.5 + 0.5 * (match_len as f32 / text_len as f32);
+}
+
+pub struct FuzzyScorer(String);
+impl Scorer for FuzzyScorer {
+    fn score(&self, text: &str) -> f32 {
+        return fuzzy_compare(&self.0, text);
+    }
+}
+
+pub struct PrefixScorer(String);
+impl Scorer for PrefixScorer {
+    fn score(&self, text: &str) -> f32 {
+        if !text.starts_with(&self.0) {
+            return 0.0;
+        }
+        return strict_match_score(self.0.len(), text.len());
+    }
+}
+
+pub struct SubstringScorer(String);
+impl Scorer for SubstringScorer {
+    fn score(&self, text: &str) -> f32 {
+        if !text.contains(&self.0) {
+            return 0.0;
+        }
+        return strict_match_score(self.0.len(), text.len());
+    }
+}
+
+pub struct LevenshteinScorer(Vec<char>);
+impl Scorer for LevenshteinScorer {
+    fn score(&self, text: &str) -> f32 {
+        let text = text.chars().collect::<Vec<char>>();
+        let max_len = self.0.len().max(text.len());
+        if max_len == 0 {
+            return 1.0;
+        }
+        return 1.0 - damerau_levenshtein(&self.0, &text) as f32 / max_len as f32;
+    }
+}
+
+pub fn damerau_levenshtein(a: &[char], b: &[char]) -> usize {
+    //! Edit distance, where insertions, deletions, substitutions and adjacent transpositions cost 1.
+    let mut dist = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in dist[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            dist[i][j] = (dist[i - 1][j] + 1)
+                .min(dist[i][j - 1] + 1)
+                .min(dist[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                dist[i][j] = dist[i][j].min(dist[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    return dist[a.len()][b.len()];
+}
+
+pub struct RegexScorer(Regex);
+impl RegexScorer {
+    pub fn new(search_term: &str) -> RegexScorer {
+        //! An invalid pattern is matched literally.
+        return RegexScorer(match Regex::new(search_term) {
+            Ok(regex) => regex,
+            Err(msg) => {
+                printwarn!("Invalid regex, matching '{search_term}' literally: {msg}");
+                Regex::new(&regex::escape(search_term)).unwrap()
+            }
+        });
+    }
+}
+impl Scorer for RegexScorer {
+    fn score(&self, text: &str) -> f32 {
+        return match self.0.find(text) {
+            Some(found) => strict_match_score(found.len(), text.len()),
+            None => 0.0,
+        };
+    }
+}
+
+/**
+ * Scores packages against a single search term, using the strategy and mode set in SearchOpts.
+ * Scores are between 0 and 100. Only exact name matches score 100.
+ * If search_term is a glob or regex, every matching name is kept and none score 100.
+ */
+pub struct TermScorer<'a> {
+    search_term: &'a str,
+    opts: &'a SearchOpts,
+    scorer: Box<dyn Scorer>,
+    pattern: Option<Box<dyn Scorer>>,
+    // (Case sensitive, lowercase) scorers for each word in search_term. Only used when searching descriptions.
+    token_scorers: Vec<(Box<dyn Scorer>, Box<dyn Scorer>)>,
+}
+
+impl<'a> TermScorer<'a> {
+    pub fn new(search_term: &'a str, opts: &'a SearchOpts) -> TermScorer<'a> {
+        let token_scorers = if opts.search_descriptions {
+            search_term.split_whitespace()
+                .map(|token| (opts.scorer.build(token), opts.scorer.build(&token.to_lowercase())))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let pattern = PatternKind::of(search_term, opts).map(|kind| kind.build(search_term));
+        return TermScorer { search_term, opts, scorer: opts.scorer.build(search_term), pattern, token_scorers };
+    }
+    pub fn score(&self, pkg: &QueryResult) -> Option<i32> {
+        /*!
+         * Returns None if pkg scores below opts.threshold.
+         * When searching descriptions, every word must match either the name or a word in the description.
+         */
+        // Patterns ignore the threshold, any match is kept.
+        if let Some(pattern) = &self.pattern {
+            let score = pattern.score(&pkg.pkg_name);
+            if score <= 0.0 {
+                return None;
+            }
+            return Some((score * 100.0).min(99.0) as i32);
+        }
+        // Ensure exact matches return 100%.
+        // Just in case any weird floating point nonsense happens.
+        if self.search_term == pkg.pkg_name {
+            return Some(100);
+        }
+        if self.opts.exact {
+            return None;
+        }
+        let score = if self.opts.search_descriptions {
+            self.score_with_description(pkg)?
+        } else {
+            self.scorer.score(&pkg.pkg_name)
+        };
+
+        if score < self.opts.threshold {
+            return None;
+        }
+        // 100 is reserved for exact matches.
+        return Some((score * 100.0).min(99.0) as i32);
+    }
+    fn score_with_description(&self, pkg: &QueryResult) -> Option<f32> {
+        let total_weight = self.opts.name_weight + self.opts.description_weight;
+        if total_weight <= 0.0 || self.token_scorers.len() == 0 {
+            return None;
+        }
+        let desc_words = description_words(&pkg.pkg_description);
+
+        let mut score = 0.0;
+        for (scorer, lowercase_scorer) in self.token_scorers.iter() {
+            let name_score = scorer.score(&pkg.pkg_name);
+            let desc_score = desc_words.iter().map(|word| lowercase_scorer.score(word)).fold(0.0, f32::max);
+
+            if name_score.max(desc_score) < self.opts.threshold {
+                return None;
+            }
+            score += (self.opts.name_weight * name_score + self.opts.description_weight * desc_score) / total_weight;
+        }
+        return Some(score / self.token_scorers.len() as f32);
+    }
+}
+
+pub fn score_results(listing: &[QueryResult], search_term: &str, opts: &SearchOpts) -> Vec<QueryResult> {
+    //! Scores each result against search_term, dropping those below opts.threshold.
+    let scorer = TermScorer::new(search_term, opts);
+    return listing.iter().filter_map(|res| {
+        let score = scorer.score(res)?;
+        Some(QueryResult { score, ..res.clone() })
+    }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scorer::*;
+
+    fn names(listing: &[&str], search_term: &str, opts: &SearchOpts) -> Vec<String> {
+        let listing = listing.iter().map(|name| QueryResult { pkg_name: name.to_string(), ..Default::default() }).collect::<Vec<QueryResult>>();
+        return score_results(&listing, search_term, opts).into_iter().map(|p| p.pkg_name).collect();
+    }
+
+    #[test]
+    fn test_strict_scorers() {
+        let listing = ["python3", "python3-devel", "cpython", "font-misc-misc"];
+        let opts = |scorer| SearchOpts { scorer, ..Default::default() };
+        assert_eq!(names(&listing, "python3", &opts(ScorerKind::Prefix)), vec!["python3", "python3-devel"]);
+        assert_eq!(names(&listing, "python", &opts(ScorerKind::Substring)), vec!["python3", "python3-devel", "cpython"]);
+        assert_eq!(names(&listing, "^python3-.*-?devel$", &opts(ScorerKind::Regex)), vec!["python3-devel"]);
+    }
+    #[test]
+    fn test_exact_match_scores_100() {
+        for scorer in [ScorerKind::Fuzzy, ScorerKind::Prefix, ScorerKind::Substring, ScorerKind::Levenshtein, ScorerKind::Regex] {
+            let opts = SearchOpts { scorer, ..Default::default() };
+            let listing = [QueryResult { pkg_name: "vim".into(), ..Default::default() }, QueryResult { pkg_name: "vim-huge".into(), ..Default::default() }];
+            let res = score_results(&listing, "vim", &opts);
+            assert_eq!(res[0].score, 100, "{scorer}");
+            assert!(res[1].score < 100, "{scorer}");
+        }
+    }
+    #[test]
+    fn test_damerau_levenshtein() {
+        let dist = |a: &str, b: &str| damerau_levenshtein(&a.chars().collect::<Vec<char>>(), &b.chars().collect::<Vec<char>>());
+        assert_eq!(dist("blender", "blender"), 0);
+        assert_eq!(dist("bledner", "blender"), 1);
+        assert_eq!(dist("blende", "blender"), 1);
+        assert_eq!(dist("", "vim"), 3);
+    }
+    #[test]
+    fn test_threshold() {
+        let listing = ["blender", "blend2d"];
+        let strict = SearchOpts { scorer: ScorerKind::Levenshtein, threshold: 0.8, ..Default::default() };
+        assert_eq!(names(&listing, "bledner", &strict), vec!["blender"]);
+        let loose = SearchOpts { scorer: ScorerKind::Levenshtein, threshold: 0.5, ..Default::default() };
+        assert_eq!(names(&listing, "bledner", &loose), vec!["blender", "blend2d"]);
+    }
+    #[test]
+    fn test_pattern_keeps_every_match() {
+        let listing = ["python3", "python3-devel", "python3-lxml-devel", "font-misc-misc", "font-util"];
+        let opts = SearchOpts::default();
+        assert_eq!(names(&listing, "^python3-.*-?devel$", &opts), vec!["python3-devel", "python3-lxml-devel"]);
+        assert_eq!(names(&listing, "font-*", &opts), vec!["font-misc-misc", "font-util"]);
+
+        // Forced regex, which would otherwise be an exact match.
+        let opts = SearchOpts { pattern: Some(PatternKind::Regex), ..Default::default() };
+        let res = score_results(&listing.iter().map(|name| QueryResult { pkg_name: name.to_string(), ..Default::default() }).collect::<Vec<QueryResult>>(), "python3", &opts);
+        assert_eq!(res.len(), 3);
+        assert!(res.iter().all(|p| p.score < 100));
+    }
+    #[test]
+    fn test_exact_mode() {
+        let listing = ["vim", "vim-huge", "neovim"];
+        let opts = SearchOpts { exact: true, threshold: 0.0, ..Default::default() };
+        assert_eq!(names(&listing, "vim", &opts), vec!["vim"]);
+        assert!(names(&listing, "vi", &opts).is_empty());
+    }
+}
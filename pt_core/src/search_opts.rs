@@ -0,0 +1,107 @@
+use mythos_core::{printerror, printwarn};
+
+use crate::{config::{Config, SearchConfig}, pattern::PatternKind, scorer::ScorerKind, SearchOpts};
+
+// Minimum score package must get using fuzzy find to be included in results.
+const THRESHOLD: f32 = 0.3;
+const NAME_WEIGHT: f32 = 0.7;
+const DESCRIPTION_WEIGHT: f32 = 0.3;
+
+impl SearchOpts {
+    pub fn load() -> SearchOpts {
+        //! Defaults, overridden by the [search] section of the config file.
+        return SearchOpts::from_config(&Config::load().search);
+    }
+    pub fn from_config(config: &SearchConfig) -> SearchOpts {
+        let mut opts = SearchOpts::default();
+        if let Some(scorer) = &config.scorer {
+            match scorer.parse::<ScorerKind>() {
+                Ok(scorer) => opts.scorer = scorer,
+                Err(msg) => printerror!("{msg}"),
+            }
+        }
+        match config.threshold {
+            Some(threshold) if (0.0..=1.0).contains(&threshold) => opts.threshold = threshold,
+            Some(threshold) => printwarn!("Threshold in config must be between 0 and 1, got '{threshold}'. Using {}", opts.threshold),
+            None => (),
+        }
+        opts.search_descriptions = config.descriptions.unwrap_or(opts.search_descriptions);
+        opts.name_weight = config.name_weight.unwrap_or(opts.name_weight);
+        opts.description_weight = config.description_weight.unwrap_or(opts.description_weight);
+        return opts;
+    }
+    pub fn parse_arg(&mut self, arg: &str) -> bool {
+        /*!
+         * Apply a search related cli arg.
+         * Returns false if arg isn't one, so callers can keep checking their own args.
+         */
+        if arg == "-d" || arg == "--descriptions" {
+            self.search_descriptions = true;
+            return true;
+        }
+        if arg == "-e" || arg == "--exact" {
+            self.exact = true;
+            return true;
+        }
+        if arg == "--glob" {
+            self.pattern = Some(PatternKind::Glob);
+            return true;
+        }
+        if arg == "--regex" {
+            self.pattern = Some(PatternKind::Regex);
+            return true;
+        }
+        if let Some(scorer) = arg.strip_prefix("--scorer=") {
+            match scorer.parse::<ScorerKind>() {
+                Ok(scorer) => self.scorer = scorer,
+                Err(msg) => printerror!("{msg}"),
+            }
+            return true;
+        }
+        if let Some(threshold) = arg.strip_prefix("--threshold=") {
+            match threshold.parse::<f32>() {
+                Ok(threshold) if (0.0..=1.0).contains(&threshold) => self.threshold = threshold,
+                _ => printerror!("Threshold must be a number between 0 and 1, got '{threshold}'"),
+            }
+            return true;
+        }
+        return false;
+    }
+}
+impl Default for SearchOpts {
+    fn default() -> Self {
+        return SearchOpts {
+            threshold: THRESHOLD,
+            scorer: ScorerKind::default(),
+            search_descriptions: false,
+            name_weight: NAME_WEIGHT,
+            description_weight: DESCRIPTION_WEIGHT,
+            exact: false,
+            pattern: None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::SearchConfig, scorer::ScorerKind, SearchOpts};
+
+    #[test]
+    fn test_cli_overrides_config() {
+        let config = SearchConfig { scorer: Some("prefix".into()), threshold: Some(0.5), ..Default::default() };
+        let mut opts = SearchOpts::from_config(&config);
+        assert_eq!(opts.scorer, ScorerKind::Prefix);
+        assert_eq!(opts.threshold, 0.5);
+
+        assert!(opts.parse_arg("--scorer=levenshtein"));
+        assert!(opts.parse_arg("--threshold=0.8"));
+        assert!(!opts.parse_arg("-n"));
+        assert_eq!(opts.scorer, ScorerKind::Levenshtein);
+        assert_eq!(opts.threshold, 0.8);
+    }
+    #[test]
+    fn test_config_threshold_out_of_range() {
+        let opts = SearchOpts::from_config(&SearchConfig { threshold: Some(30.0), ..Default::default() });
+        assert_eq!(opts.threshold, SearchOpts::default().threshold);
+    }
+}
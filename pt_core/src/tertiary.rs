@@ -0,0 +1,375 @@
+/*!
+ * Package managers other than xbps and charon (tertiary package managers).
+ * These are only searched for names that neither xbps nor charon know about.
+ * Managers are searched in order (flatpak, then each [[external]] in the config file), and the
+ * first one that has the package is used.
+ *
+ * flatpak: Searches the appstream metadata flatpak caches for each remote.
+ * <flatpak dir>/appstream/<remote>/<arch>/active/appstream.xml(.gz)
+ * System installations use /var/lib/flatpak, user installations use ~/.local/share/flatpak.
+ * Appstream lists every app a remote has, installed apps are read from `flatpak list --app`.
+ *
+ * external: Runs the search command from the config file, and reads packages from its output using regex.
+ */
+use std::{fmt::Display, fs, io::{self, Read}, path::{Path, PathBuf}, process::ExitStatus};
+
+use flate2::read::GzDecoder;
+use mythos_core::{cli::get_user_permission, printerror, printinfo, printwarn};
+use regex::Regex;
+
+use crate::{config::{Config, ExternalConfig}, utils::run_cmd, PkgOrigin, QueryResult};
+
+pub const FLATPAK_SYSTEM_DIR: &str = "/var/lib/flatpak";
+
+/**
+ * A package provided by a tertiary package manager.
+ *
+ * pkg_id: Name the package manager knows pkg by, e.g. org.blender.Blender.
+ * install_cmd: Command that installs pkg, e.g. flatpak install -y flathub org.blender.Blender.
+ * remove_cmd: Command that removes pkg.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TertiaryPkg {
+    pub manager: String,
+    pub pkg_id: String,
+    pub pkg_name: String,
+    pub pkg_description: String,
+    pub install_cmd: Vec<String>,
+    pub remove_cmd: Vec<String>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TertiaryAction {
+    Install,
+    Remove,
+}
+
+impl TertiaryPkg {
+    pub fn matches(&self, search_term: &str) -> bool {
+        //! Case insensitive match against pkg_id, the last part of pkg_id or pkg_name.
+        let search_term = search_term.to_lowercase();
+        let pkg_id = self.pkg_id.to_lowercase();
+        return pkg_id == search_term
+            || pkg_id.rsplit('.').next() == Some(&search_term)
+            || self.pkg_name.to_lowercase() == search_term;
+    }
+    pub fn is_installed(&self, installed_flatpaks: &[String]) -> bool {
+        //! Only flatpaks can be checked, packages from external managers are assumed to be installed.
+        return self.manager != "flatpak" || installed_flatpaks.contains(&self.pkg_id);
+    }
+    pub fn cmd(&self, action: TertiaryAction) -> &[String] {
+        return match action {
+            TertiaryAction::Install => &self.install_cmd,
+            TertiaryAction::Remove => &self.remove_cmd,
+        };
+    }
+    pub fn run(&self, action: TertiaryAction) -> io::Result<ExitStatus> {
+        return run_cmd(self.cmd(action));
+    }
+}
+impl From<&TertiaryPkg> for QueryResult {
+    fn from(value: &TertiaryPkg) -> Self {
+        return QueryResult {
+            pkg_name: value.pkg_id.to_owned(),
+            pkg_description: value.pkg_description.to_owned(),
+            origin: PkgOrigin::External(value.manager.to_owned()),
+            ..Default::default()
+        };
+    }
+}
+impl Display for TertiaryPkg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{} ({}): {}", self.pkg_id, self.manager, self.pkg_description);
+    }
+}
+
+/**
+ * A package manager declared in the [[external]] section of the config file.
+ */
+#[derive(Debug, Clone)]
+pub struct ExternalManager {
+    name: String,
+    search: String,
+    regex: Regex,
+    install: String,
+    remove: String,
+}
+
+impl ExternalManager {
+    pub fn new(config: &ExternalConfig) -> Result<ExternalManager, String> {
+        let regex = Regex::new(&config.regex).map_err(|err| format!("Invalid regex for {}: {err}", config.name))?;
+        if !regex.capture_names().any(|x| x == Some("name")) {
+            return Err(format!("Regex for {} has no 'name' group", config.name));
+        }
+        return Ok(ExternalManager {
+            name: config.name.to_owned(),
+            search: config.search.to_owned(),
+            regex,
+            install: config.install.to_owned(),
+            remove: config.remove.to_owned(),
+        });
+    }
+    pub fn load() -> Vec<ExternalManager> {
+        //! Managers declared in the config file. Invalid ones are skipped.
+        return Config::load().external.iter().filter_map(|config| match ExternalManager::new(config) {
+            Ok(manager) => Some(manager),
+            Err(msg) => {
+                printerror!("{msg}");
+                None
+            }
+        }).collect();
+    }
+    pub fn search(&self, search_term: &str) -> Vec<TertiaryPkg> {
+        //! Run the search command. If it fails, nothing was found.
+        let cmd = fill_cmd(&self.search, search_term);
+        let (program, args) = match cmd.split_first() {
+            Some(cmd) => cmd,
+            None => return Vec::new(),
+        };
+        return match duct::cmd(program, args).stdout_capture().stderr_null().unchecked().run() {
+            Ok(output) => self.parse_output(search_term, &String::from_utf8_lossy(&output.stdout)),
+            Err(msg) => {
+                printwarn!("Could not run {}: {msg}", self.name);
+                Vec::new()
+            }
+        };
+    }
+    pub fn parse_output(&self, search_term: &str, output: &str) -> Vec<TertiaryPkg> {
+        //! Packages in output that search_term names.
+        return output.lines().filter_map(|line| {
+            let captures = self.regex.captures(line)?;
+            let pkg_id = captures.name("name")?.as_str().to_string();
+            let pkg = TertiaryPkg {
+                manager: self.name.to_owned(),
+                pkg_name: pkg_id.to_owned(),
+                pkg_description: captures.name("description").map(|x| x.as_str().trim().to_string()).unwrap_or_default(),
+                install_cmd: fill_cmd(&self.install, &pkg_id),
+                remove_cmd: fill_cmd(&self.remove, &pkg_id),
+                pkg_id,
+            };
+            if pkg.matches(search_term) { Some(pkg) } else { None }
+        }).collect();
+    }
+}
+
+pub fn query_tertiary(search_terms: &[&str], externals: &[ExternalManager]) -> Vec<Vec<TertiaryPkg>> {
+    /*!
+     * Find packages each search term names in flatpak, or one of externals.
+     * Returns one list per search term, in the same order as search_terms.
+     */
+    return query_managers(search_terms, &list_flatpaks(), externals);
+}
+pub fn query_managers(search_terms: &[&str], flatpaks: &[TertiaryPkg], externals: &[ExternalManager]) -> Vec<Vec<TertiaryPkg>> {
+    //! Search each manager in order, stopping at the first one that has the package.
+    return search_terms.iter().map(|term| {
+        let pkgs = flatpaks.iter().filter(|p| p.matches(term)).cloned().collect::<Vec<TertiaryPkg>>();
+        if pkgs.len() > 0 {
+            return pkgs;
+        }
+        for external in externals {
+            let pkgs = external.search(term);
+            if pkgs.len() > 0 {
+                return pkgs;
+            }
+        }
+        return Vec::new();
+    }).collect();
+}
+pub fn run_tertiary(pkgs: &[TertiaryPkg], action: TertiaryAction, do_dry_run: bool, assume_yes: bool) {
+    /*!
+     * Ask before running action on each package, since each one goes to a different package manager.
+     * Failures are reported and skipped, so one bad package manager doesn't stop the rest.
+     */
+    let (verb, doing) = match action {
+        TertiaryAction::Install => ("Install", "installing"),
+        TertiaryAction::Remove => ("Remove", "removing"),
+    };
+    for pkg in pkgs {
+        if !get_user_permission(assume_yes, &format!("{verb} {pkg}?")) {
+            continue;
+        }
+        if do_dry_run {
+            printinfo!("Would run: {}", pkg.cmd(action).join(" "));
+            continue;
+        }
+        match pkg.run(action) {
+            Ok(status) if status.success() => (),
+            Ok(status) => printerror!("{} exited with {status} while {doing} {}", pkg.manager, pkg.pkg_id),
+            Err(msg) => printerror!("Could not run {}: {msg}", pkg.manager),
+        }
+    }
+}
+pub fn fill_cmd(template: &str, value: &str) -> Vec<String> {
+    //! Split template on whitespace, replacing {} with value.
+    return template.split_whitespace().map(|word| word.replace("{}", value)).collect();
+}
+pub fn flatpak_dirs() -> Vec<PathBuf> {
+    //! System and user flatpak installations.
+    let mut dirs = vec![PathBuf::from(FLATPAK_SYSTEM_DIR)];
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(Path::new(&home).join(".local/share/flatpak"));
+    }
+    return dirs;
+}
+pub fn list_flatpaks() -> Vec<TertiaryPkg> {
+    //! Every app in the appstream cache of every remote. Unreadable caches are skipped.
+    let mut output: Vec<TertiaryPkg> = Vec::new();
+    for flatpak_dir in flatpak_dirs() {
+        for (remote, path) in find_appstream_files(&flatpak_dir) {
+            match read_appstream(&path).and_then(|xml| parse_appstream(&xml, &remote)) {
+                Ok(pkgs) => output.extend(pkgs),
+                Err(msg) => printwarn!("Could not read {}: {msg}", path.display()),
+            }
+        }
+    }
+    return output;
+}
+pub fn list_installed_flatpaks() -> Vec<String> {
+    //! App ids of every installed flatpak app, system and user. Empty if flatpak can't be run.
+    return match duct::cmd("flatpak", ["list", "--app", "--columns=application"]).stdout_capture().stderr_null().unchecked().run() {
+        Ok(output) => parse_flatpak_list(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    };
+}
+pub fn parse_flatpak_list(output: &str) -> Vec<String> {
+    return output.lines().map(|x| x.trim()).filter(|x| !x.is_empty()).map(|x| x.to_string()).collect();
+}
+pub fn find_appstream_files(flatpak_dir: &Path) -> Vec<(String, PathBuf)> {
+    //! (remote, appstream file) for each remote's active appstream cache.
+    let mut output: Vec<(String, PathBuf)> = Vec::new();
+    let remotes = match fs::read_dir(flatpak_dir.join("appstream")) {
+        Ok(remotes) => remotes,
+        Err(_) => return output,
+    };
+    for remote in remotes.flatten() {
+        let arches = match fs::read_dir(remote.path()) {
+            Ok(arches) => arches,
+            Err(_) => continue,
+        };
+        for arch in arches.flatten() {
+            let active = arch.path().join("active");
+            let path = [active.join("appstream.xml"), active.join("appstream.xml.gz")]
+                .into_iter()
+                .find(|x| x.is_file());
+            if let Some(path) = path {
+                output.push((remote.file_name().to_string_lossy().to_string(), path));
+            }
+        }
+    }
+    output.sort();
+    return output;
+}
+pub fn read_appstream(path: &Path) -> io::Result<String> {
+    //! Read appstream file, decompressing it if needed.
+    let mut output = String::new();
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        GzDecoder::new(fs::File::open(path)?).read_to_string(&mut output)?;
+    } else {
+        fs::File::open(path)?.read_to_string(&mut output)?;
+    }
+    return Ok(output);
+}
+pub fn parse_appstream(xml: &str, remote: &str) -> io::Result<Vec<TertiaryPkg>> {
+    /*!
+     * Read the apps listed in an appstream file.
+     * The flatpak ref is read from <bundle type="flatpak">app/<id>/<arch>/<branch></bundle>.
+     * Runtimes and components without a flatpak bundle are skipped.
+     */
+    let doc = roxmltree::Document::parse(xml).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut output: Vec<TertiaryPkg> = Vec::new();
+
+    for component in doc.root_element().children().filter(|x| x.has_tag_name("component")) {
+        let bundle = component.children().find(|x| x.has_tag_name("bundle") && x.attribute("type") == Some("flatpak"));
+        let pkg_id = match bundle.and_then(|x| x.text()).and_then(|x| x.trim().strip_prefix("app/")) {
+            Some(flatpak_ref) => flatpak_ref.split('/').next().unwrap_or_default().to_string(),
+            None => continue,
+        };
+        // Untranslated text, e.g. <name> rather than <name xml:lang="de">.
+        let text = |tag: &str| component.children()
+            .find(|x| x.has_tag_name(tag) && x.attributes().all(|attr| attr.name() != "lang"))
+            .and_then(|x| x.text())
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        output.push(TertiaryPkg {
+            manager: "flatpak".into(),
+            pkg_name: text("name"),
+            pkg_description: text("summary"),
+            install_cmd: vec!["flatpak".into(), "install".into(), "-y".into(), remote.into(), pkg_id.to_owned()],
+            remove_cmd: vec!["flatpak".into(), "uninstall".into(), "-y".into(), pkg_id.to_owned()],
+            pkg_id,
+        });
+    }
+    return Ok(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tertiary::*;
+
+    const APPSTREAM: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<components version="0.8" origin="flathub">
+  <component type="desktop">
+    <id>org.blender.Blender.desktop</id>
+    <name>Blender</name>
+    <name xml:lang="de">Blender DE</name>
+    <summary>Free and open source 3D creation suite</summary>
+    <bundle type="flatpak" runtime="org.freedesktop.Platform/x86_64/23.08" sdk="org.freedesktop.Sdk/x86_64/23.08">app/org.blender.Blender/x86_64/stable</bundle>
+  </component>
+  <component type="runtime">
+    <id>org.freedesktop.Platform</id>
+    <name>Freedesktop Platform</name>
+    <bundle type="flatpak">runtime/org.freedesktop.Platform/x86_64/23.08</bundle>
+  </component>
+  <component type="desktop-application">
+    <id>com.spotify.Client</id>
+    <name>Spotify</name>
+    <summary>Online music streaming service</summary>
+    <bundle type="flatpak">app/com.spotify.Client/x86_64/stable</bundle>
+  </component>
+</components>"#;
+
+    #[test]
+    fn test_parse_appstream() {
+        let pkgs = parse_appstream(APPSTREAM, "flathub").unwrap();
+        assert_eq!(pkgs.iter().map(|p| p.pkg_id.as_str()).collect::<Vec<&str>>(), vec!["org.blender.Blender", "com.spotify.Client"]);
+        assert_eq!(pkgs[0].pkg_name, "Blender");
+        assert_eq!(pkgs[0].install_cmd, vec!["flatpak", "install", "-y", "flathub", "org.blender.Blender"]);
+    }
+    #[test]
+    fn test_matches() {
+        let pkgs = parse_appstream(APPSTREAM, "flathub").unwrap();
+        assert!(pkgs[0].matches("blender"));
+        assert!(pkgs[0].matches("org.blender.Blender"));
+        assert!(pkgs[1].matches("spotify"));
+        assert!(!pkgs[1].matches("client-spotify"));
+    }
+    #[test]
+    fn test_installed_flatpaks() {
+        let pkgs = parse_appstream(APPSTREAM, "flathub").unwrap();
+        let installed = parse_flatpak_list("org.blender.Blender\norg.gimp.GIMP\n");
+        assert!(pkgs[0].is_installed(&installed));
+        assert!(!pkgs[1].is_installed(&installed));
+        let external = TertiaryPkg { manager: "cargo".into(), pkg_id: "ripgrep".into(), ..Default::default() };
+        assert!(external.is_installed(&installed));
+    }
+    #[test]
+    fn test_external_manager() {
+        let config = ExternalConfig {
+            name: "cargo".into(),
+            search: "cargo search --limit 10 {}".into(),
+            regex: r#"^(?P<name>\S+) = "(?P<version>[^"]*)"\s*#\s*(?P<description>.*)$"#.into(),
+            install: "cargo install {}".into(),
+            remove: "cargo uninstall {}".into(),
+        };
+        let manager = ExternalManager::new(&config).unwrap();
+        let output = "ripgrep = \"14.1.0\"    # ripgrep is a line-oriented search tool\nripgrep_all = \"0.10.6\"    # rga: ripgrep, but also search in PDFs\n... and 80 crates more";
+        let pkgs = manager.parse_output("ripgrep", output);
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].pkg_description, "ripgrep is a line-oriented search tool");
+        assert_eq!(pkgs[0].install_cmd, vec!["cargo", "install", "ripgrep"]);
+        assert_eq!(pkgs[0].remove_cmd, vec!["cargo", "uninstall", "ripgrep"]);
+
+        assert!(ExternalManager::new(&ExternalConfig { regex: "^(\\S+)".into(), ..config }).is_err());
+    }
+}
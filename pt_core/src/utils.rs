@@ -1,107 +1,77 @@
-use rust_fuzzy_search::fuzzy_compare;
+use std::{io, process::ExitStatus};
 
-use crate::QueryResult;
+use crate::{version::PkgVersion, QueryResult};
 
 
-/** 
- * Assigns a value between 0, 100
- * Returns None if value is below a given threshold
- * Ensures exact matches return 100.
- */
-fn score_result(search_term: &str, name: &str, threshold: f32) -> Option<i32> {
-    // Ensure exact matches return 100%.
-    // Just in case any weird floating point nonsense happens.
-    if search_term == name {
-        return Some(100);
-    }
-    let score = fuzzy_compare(&search_term, &name);
-    if score >= threshold {
-        return Some((score * 100.0) as i32);
-    }
-    return None;
+pub fn description_words(description: &str) -> Vec<String> {
+    //! Lowercase words in description, stripped of punctuation.
+    return description
+        .split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
 }
 /**
- * xbps-query -> <block-of-text> | parse_results -> <structured-data>
+ * Separate <name>-<version>.
+ * <name> can contain '-'
+ * Last '-' in <name_block> is considered beginning of <version>
+ * The '-' is not part of either.
  */
-pub fn parse_xbps_output(raw_results: Vec<u8>, search_term: &str, fuzzy_find_threshold: f32) -> (Vec<QueryResult>, usize) {
-    enum Mode { IsInstalled, NameBlock, Gap, Description }
-    let mut output: Vec<QueryResult> = Vec::new();
-
-    let mut traversal_mode: Mode = Mode::IsInstalled;
-    let mut is_installed: bool = false;
-    let mut name_block: String = "".into();
-    let mut index: usize;
-    let mut name: String = "".into();
-    let mut version: String = "".into();
-    let mut desc: String = "".into();
-    let mut longest_name: usize = 0;
-
-    // Query result format:
-    // [<installed>] <name_block><ws*><description>\n  
-    // <name_block> -> <name>-<version>
-    // <name> can contain '-'
-    // Last '-' in <name_block> is considered beginning of <version>
-    for ch in raw_results {
-        // Start new package
-        if ch == b'\n' {
-            match score_result(&search_term, &name, fuzzy_find_threshold) {
-                Some(score) => {
-                    if name.len() > longest_name {
-                        longest_name = name.len();
-                    }
-                    output.push(QueryResult { 
-                        is_installed, 
-                        pkg_name: name.clone(),
-                        pkg_version: version.clone(),
-                        pkg_description: desc.clone(), 
-                        score,
-                    })
-                },
-                None => ()
-            }
-            name_block.clear();
-            desc.clear();
-            traversal_mode = Mode::IsInstalled;
+pub fn split_name_block(name_block: &str) -> (String, PkgVersion) {
+    return match name_block.rsplit_once('-') {
+        Some((name, version)) => (name.to_string(), PkgVersion::parse(version)),
+        None => (name_block.to_string(), PkgVersion::default()),
+    };
+}
+pub fn pattern_name(pattern: &str) -> &str {
+    /*!
+     * Get the pkg name from an xbps dependency pattern.
+     * foo>=1.0_1, foo<2, foo-1.0_1, foo-[0-9]* -> foo
+     */
+    if let Some(index) = pattern.find(['<', '>', '=']) {
+        return &pattern[..index];
+    }
+    if let Some((name, version)) = pattern.rsplit_once('-') {
+        if version.starts_with(|ch: char| ch.is_ascii_digit() || ch == '[' || ch == '*') {
+            return name;
         }
-        match traversal_mode {
-            Mode::IsInstalled => {
-                if ch == b' ' {
-                    traversal_mode = Mode::NameBlock;
-                } else if ch == b'*' {
-                    is_installed = true;
-                } else if ch == b'-' {
-                    is_installed = false; 
-                }
-            },
-            Mode::NameBlock => {
-                if ch.is_ascii_whitespace() {
-                    traversal_mode = Mode::Gap;
-
-                    // Separate <name>-<version>
-                    index = match name_block.rfind('-') {
-                        Some(index) => index, 
-                        None => name_block.len()
-                    };
-
-                    name = name_block[0..index].to_string();
-                    version = name_block[index..].to_string();
-                } else {
-                    name_block.push(ch as char);
-                }
-            },
-            Mode::Description => {
-                desc.push(ch as char);
-            },
-            Mode::Gap => {
-                if !ch.is_ascii_whitespace() {
-                    traversal_mode = Mode::Description;
-                    desc.push(ch as char);
-                }
-            },
-        }; // end match()
-    } // end loop
-    return (output, longest_name);
+    }
+    return pattern;
 }
+pub fn plist_string(dict: &plist::Dictionary, key: &str) -> String {
+    //! Get dict[key] as a String. Missing or mistyped values become "".
+    return dict.get(key).and_then(|x| x.as_string()).unwrap_or("").to_string();
+}
+pub fn plist_strings(dict: &plist::Dictionary, key: &str) -> Vec<String> {
+    //! Get dict[key] as an array of Strings. Missing or mistyped values become [].
+    return match dict.get(key).and_then(|x| x.as_array()) {
+        Some(array) => array.iter().filter_map(|x| x.as_string()).map(|x| x.to_string()).collect(),
+        None => Vec::new(),
+    };
+}
+pub fn format_size(bytes: u64) -> String {
+    //! Human readable size, e.g. 1.5 MB.
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{bytes} B");
+    }
+    return format!("{size:.1} {}", UNITS[unit]);
+}
+pub fn run_cmd(cmd: &[String]) -> io::Result<ExitStatus> {
+    //! Output is not captured, since the package manager may prompt the user.
+    let (program, args) = match cmd.split_first() {
+        Some(cmd) => cmd,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "No command was given")),
+    };
+    return duct::cmd(program, args).unchecked().run().map(|output| output.status);
+}
+
 pub fn read_single_index(input: &str, query: &Vec<QueryResult>) -> Option<(QueryResult, usize)> {
     /*!
         * If input is a valid usize, get query[input]
@@ -117,7 +87,7 @@ pub fn read_single_index(input: &str, query: &Vec<QueryResult>) -> Option<(Query
         return None;
     }
     let output = query[num_input - 1].clone();
-    return Some((output.to_owned(), output.pkg_name.len()));
+    return Some((output.to_owned(), output.list_name().len()));
 }
 pub fn read_multiple_index(input: &str, query: &Vec<QueryResult>) -> Option<(Vec<QueryResult>, usize)> {
     let mut pkgs: Vec<QueryResult> = Vec::new();
@@ -0,0 +1,270 @@
+/*!
+ * Package versions, as used by xbps: <version>_<revision> (e.g. 1.2.3_4).
+ *
+ * Ordering follows xbps_cmpver (dewey comparison, taken from pkgsrc):
+ * - Versions are split into numbers, and compared component by component. Missing components count as 0.
+ * - '.' and "pl" count as a 0 component, so 1.2 == 1.2.0.
+ * - alpha, beta and pre/rc sort before a release: 1.0alpha < 1.0beta < 1.0rc1 < 1.0
+ * - Any other letter counts as '.' followed by its place in the alphabet: 1.2a == 1.2.1
+ * - Revisions are only compared if the versions are equal.
+ *
+ * Package specs constrain the version of a package, like xbps' package patterns:
+ * foo>=1.2, foo<2.0, foo>=1.2<2.0, foo-1.2_1 (exactly that version and revision)
+ */
+use std::{cmp::Ordering, fmt::Display};
+
+use serde_derive::{Deserialize, Serialize};
+
+const ALPHA: i64 = -3;
+const BETA: i64 = -2;
+const RC: i64 = -1;
+const DOT: i64 = 0;
+
+/**
+ * revision: None if the version had none, e.g. programs installed by charon. Compared as 0.
+ */
+#[derive(Debug, Clone, Default, Eq, Deserialize, Serialize)]
+pub struct PkgVersion {
+    pub version: String,
+    pub revision: Option<u32>,
+}
+
+impl PkgVersion {
+    pub fn parse(version: &str) -> PkgVersion {
+        //! Split off the revision. Anything after the last '_' that isn't a number is part of version.
+        if let Some((version, revision)) = version.rsplit_once('_') {
+            if let Ok(revision) = revision.parse::<u32>() {
+                return PkgVersion { version: version.into(), revision: Some(revision) };
+            }
+        }
+        return PkgVersion { version: version.into(), revision: None };
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.version.is_empty() && self.revision.is_none();
+    }
+    fn components(&self) -> Vec<i64> {
+        let modifiers = [("alpha", ALPHA), ("beta", BETA), ("pre", RC), ("rc", RC), ("pl", DOT), (".", DOT)];
+        let version = self.version.to_lowercase();
+        let mut rest = version.as_str();
+        let mut output: Vec<i64> = Vec::new();
+
+        while let Some(ch) = rest.chars().next() {
+            if ch.is_ascii_digit() {
+                let end = rest.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(rest.len());
+                output.push(rest[..end].parse::<i64>().unwrap_or(i64::MAX));
+                rest = &rest[end..];
+                continue;
+            }
+            if let Some((modifier, value)) = modifiers.iter().find(|(modifier, _)| rest.starts_with(modifier)) {
+                output.push(*value);
+                rest = &rest[modifier.len()..];
+                continue;
+            }
+            if ch.is_ascii_lowercase() {
+                output.push(DOT);
+                output.push((ch as u8 - b'a') as i64 + 1);
+            }
+            // Anything else (e.g. '+' or '_') is ignored.
+            rest = &rest[ch.len_utf8()..];
+        }
+        return output;
+    }
+}
+impl Ord for PkgVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = (self.components(), other.components());
+        for i in 0..a.len().max(b.len()) {
+            let ord = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        return self.revision.unwrap_or(0).cmp(&other.revision.unwrap_or(0));
+    }
+}
+impl PartialOrd for PkgVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+impl PartialEq for PkgVersion {
+    fn eq(&self, other: &Self) -> bool {
+        //! Equal by xbps' rules, e.g. 1.2_1 == 1.2.0_1.
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+impl Display for PkgVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self.revision {
+            Some(revision) => write!(f, "{}_{revision}", self.version),
+            None => write!(f, "{}", self.version),
+        };
+    }
+}
+impl From<&str> for PkgVersion {
+    fn from(value: &str) -> Self {
+        return PkgVersion::parse(value);
+    }
+}
+impl PartialEq<&str> for PkgVersion {
+    fn eq(&self, other: &&str) -> bool {
+        //! Compares the text, not the version. Mostly useful in tests.
+        return match self.revision {
+            Some(revision) => other.rsplit_once('_').is_some_and(|(version, rest)| version == self.version && rest.parse::<u32>() == Ok(revision)),
+            None => self.version == *other,
+        };
+    }
+}
+
+/**
+ * A package name, and the versions of it that are accepted.
+ * Every constraint must be satisfied.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PkgSpec {
+    pub pkg_name: String,
+    pub constraints: Vec<(VersionOp, PkgVersion)>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+impl PkgSpec {
+    pub fn parse(spec: &str) -> Option<PkgSpec> {
+        /*!
+         * Returns None if spec is a plain name, without any constraints.
+         * <name>-<version>_<revision> is only treated as a spec if it has a revision, since names can contain '-'.
+         */
+        let index = match spec.find(['<', '>']) {
+            Some(index) => index,
+            None => {
+                let (pkg_name, version) = spec.rsplit_once('-')?;
+                let version = PkgVersion::parse(version);
+                if pkg_name.is_empty() || version.revision.is_none() || !version.version.starts_with(|ch: char| ch.is_ascii_digit()) {
+                    return None;
+                }
+                return Some(PkgSpec { pkg_name: pkg_name.into(), constraints: vec![(VersionOp::Eq, version)] });
+            }
+        };
+        let mut constraints: Vec<(VersionOp, PkgVersion)> = Vec::new();
+        let mut rest = &spec[index..];
+        while !rest.is_empty() {
+            let (op, len) = if rest.starts_with(">=") {
+                (VersionOp::Ge, 2)
+            } else if rest.starts_with("<=") {
+                (VersionOp::Le, 2)
+            } else if rest.starts_with('>') {
+                (VersionOp::Gt, 1)
+            } else {
+                (VersionOp::Lt, 1)
+            };
+            rest = &rest[len..];
+            let end = rest.find(['<', '>']).unwrap_or(rest.len());
+            constraints.push((op, PkgVersion::parse(&rest[..end])));
+            rest = &rest[end..];
+        }
+        return Some(PkgSpec { pkg_name: spec[..index].into(), constraints });
+    }
+    pub fn is_valid(&self) -> bool {
+        //! False if the name or any of the versions are missing, e.g. foo>= or >=1.0
+        return !self.pkg_name.is_empty() && self.constraints.iter().all(|(_, version)| !version.is_empty());
+    }
+    pub fn matches(&self, version: &PkgVersion) -> bool {
+        return self.constraints.iter().all(|(op, constraint)| {
+            let ord = version.cmp(constraint);
+            match op {
+                VersionOp::Lt => ord == Ordering::Less,
+                VersionOp::Le => ord != Ordering::Greater,
+                VersionOp::Gt => ord == Ordering::Greater,
+                VersionOp::Ge => ord != Ordering::Less,
+                VersionOp::Eq => ord == Ordering::Equal,
+            }
+        });
+    }
+}
+impl Display for PkgSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //! Formatted as an xbps package pattern, which xbps-install accepts in place of a name.
+        write!(f, "{}", self.pkg_name)?;
+        for (op, version) in self.constraints.iter() {
+            write!(f, "{op}{version}")?;
+        }
+        return Ok(());
+    }
+}
+impl Display for VersionOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}", match self {
+            VersionOp::Lt => "<",
+            VersionOp::Le => "<=",
+            VersionOp::Gt => ">",
+            VersionOp::Ge => ">=",
+            VersionOp::Eq => "-",
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::version::*;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        return PkgVersion::parse(a).cmp(&PkgVersion::parse(b));
+    }
+
+    #[test]
+    fn test_parse() {
+        let version = PkgVersion::parse("1.2.3_4");
+        assert_eq!(version.version, "1.2.3");
+        assert_eq!(version.revision, Some(4));
+        assert_eq!(version.to_string(), "1.2.3_4");
+
+        let version = PkgVersion::parse("0.3.0");
+        assert_eq!(version.revision, None);
+        assert_eq!(version.to_string(), "0.3.0");
+        assert!(PkgVersion::parse("").is_empty());
+        assert_eq!(PkgVersion::parse("1.2_3"), "1.2_3");
+        assert_ne!(PkgVersion::parse("1.2_3"), "1.2");
+        assert_ne!(PkgVersion::parse("1.2"), "1.2_0");
+    }
+    #[test]
+    fn test_cmpver() {
+        assert_eq!(cmp("1.10_1", "1.9_1"), Ordering::Greater);
+        assert_eq!(cmp("1.2_1", "1.2.0_1"), Ordering::Equal);
+        assert_eq!(cmp("1.2_2", "1.2_10"), Ordering::Less);
+        // Revision only matters if the versions are equal.
+        assert_eq!(cmp("1.3_1", "1.2_10"), Ordering::Greater);
+        assert_eq!(cmp("1.0alpha_1", "1.0beta_1"), Ordering::Less);
+        assert_eq!(cmp("1.0rc1_1", "1.0_1"), Ordering::Less);
+        assert_eq!(cmp("1.0pre1_1", "1.0rc1_1"), Ordering::Equal);
+        assert_eq!(cmp("1.2a_1", "1.2.1_1"), Ordering::Equal);
+        assert_eq!(cmp("1.2b_1", "1.2a_1"), Ordering::Greater);
+        assert_eq!(cmp("2024.01.31_1", "2023.12.01_3"), Ordering::Greater);
+    }
+    #[test]
+    fn test_spec() {
+        assert_eq!(PkgSpec::parse("python3-devel"), None);
+        assert_eq!(PkgSpec::parse("font-3270"), None);
+
+        let spec = PkgSpec::parse("foo-1.2_1").unwrap();
+        assert_eq!(spec.pkg_name, "foo");
+        assert!(spec.matches(&"1.2_1".into()));
+        assert!(!spec.matches(&"1.2_2".into()));
+
+        let spec = PkgSpec::parse("foo>=1.2<2.0").unwrap();
+        assert_eq!(spec.pkg_name, "foo");
+        assert_eq!(spec.to_string(), "foo>=1.2<2.0");
+        assert!(spec.matches(&"1.2_1".into()));
+        assert!(spec.matches(&"1.10_1".into()));
+        assert!(!spec.matches(&"2.0_1".into()));
+        assert!(!spec.matches(&"1.1_3".into()));
+
+        assert!(PkgSpec::parse("foo<2").unwrap().matches(&"1.9_1".into()));
+        assert!(!PkgSpec::parse("foo>=").unwrap().is_valid());
+    }
+}
@@ -0,0 +1,211 @@
+/*!
+ * Every xbps subprocess is built and run through XbpsCommand.
+ *
+ * LC_ALL=C is always set, so xbps prints the same English messages regardless of the user's
+ * locale, and they can be matched reliably.
+ *
+ * Transactions (xbps-install, xbps-remove) read stdout and stderr separately, each on its own
+ * thread. Lines are sent over a channel as they arrive, and parsed into XbpsEvents.
+ */
+use std::{fmt::Display, io::{self, BufRead, BufReader, Read}, process::{Child, Command, ExitStatus, Stdio}, sync::mpsc, thread};
+
+use duct::cmd;
+
+use crate::xbps_events::{parse_event, OutputLine, XbpsEvent};
+
+const LOCALE: (&str, &str) = ("LC_ALL", "C");
+
+/**
+ * A single xbps invocation, e.g. xbps-install -Sy blender
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct XbpsCommand {
+    program: String,
+    args: Vec<String>,
+}
+/**
+ * Captured output of a finished command.
+ */
+#[derive(Debug, Clone)]
+pub struct XbpsOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+/**
+ * Events of a running (or scripted) transaction. Iterate over it to wait for the process to finish.
+ * command: What was run, for messages. e.g. xbps-install -Sy blender
+ */
+pub struct Transaction {
+    pub command: String,
+    lines: mpsc::Receiver<OutputLine>,
+    child: Option<Child>,
+    exit_code: Option<Option<i32>>,
+}
+
+impl XbpsCommand {
+    pub fn new(program: &str) -> XbpsCommand {
+        return XbpsCommand { program: program.into(), args: Vec::new() };
+    }
+    pub fn arg(mut self, arg: &str) -> XbpsCommand {
+        self.args.push(arg.into());
+        return self;
+    }
+    pub fn args<T: AsRef<str>>(mut self, args: &[T]) -> XbpsCommand {
+        self.args.extend(args.iter().map(|x| x.as_ref().to_string()));
+        return self;
+    }
+    pub fn dry_run(self, do_dry_run: bool) -> XbpsCommand {
+        //! Adds -n, which makes xbps-install and xbps-remove show what they would do.
+        if do_dry_run {
+            return self.arg("-n");
+        }
+        return self;
+    }
+    pub fn output(&self) -> io::Result<XbpsOutput> {
+        //! Run to completion, capturing stdout and stderr. A failing exit status is not an error.
+        let output = cmd(&self.program, &self.args)
+            .env(LOCALE.0, LOCALE.1)
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()?;
+        return Ok(XbpsOutput {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    pub fn reader(&self) -> io::Result<impl BufRead> {
+        //! Stream stdout, e.g. for long listings. stderr is discarded.
+        let reader = cmd(&self.program, &self.args)
+            .env(LOCALE.0, LOCALE.1)
+            .stderr_null()
+            .unchecked()
+            .reader()?;
+        return Ok(BufReader::new(reader));
+    }
+    pub fn spawn(&self) -> io::Result<Transaction> {
+        //! Start a transaction. stdin is inherited, in case xbps asks the user anything.
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .env(LOCALE.0, LOCALE.1)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let (sender, receiver) = mpsc::channel::<OutputLine>();
+        if let Some(stdout) = child.stdout.take() {
+            let sender = sender.clone();
+            thread::spawn(move || send_lines(stdout, sender, OutputLine::Stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            thread::spawn(move || send_lines(stderr, sender, OutputLine::Stderr));
+        }
+        return Ok(Transaction { command: self.to_string(), lines: receiver, child: Some(child), exit_code: None });
+    }
+}
+fn send_lines<R: Read>(stream: R, sender: mpsc::Sender<OutputLine>, tag: fn(String) -> OutputLine) {
+    //! Stops once the stream closes, or nobody is listening anymore.
+    let mut reader = BufReader::new(stream);
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => (),
+        };
+        let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+        if sender.send(tag(line)).is_err() {
+            return;
+        }
+    }
+}
+impl Display for XbpsCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{} {}", self.program, self.args.join(" "));
+    }
+}
+
+impl Transaction {
+    pub fn from_lines(command: &str, lines: Vec<OutputLine>, exit_code: i32) -> Transaction {
+        //! A transaction that has already finished, with the given output. Used by FakeBackend.
+        let (sender, receiver) = mpsc::channel::<OutputLine>();
+        for line in lines {
+            let _ = sender.send(line);
+        }
+        return Transaction { command: command.into(), lines: receiver, child: None, exit_code: Some(Some(exit_code)) };
+    }
+    pub fn from_output(command: &str, stdout: &str, stderr: &str, exit_code: i32) -> Transaction {
+        let lines = stdout.lines().map(|x| OutputLine::Stdout(x.into()))
+            .chain(stderr.lines().map(|x| OutputLine::Stderr(x.into())))
+            .collect();
+        return Transaction::from_lines(command, lines, exit_code);
+    }
+    fn wait(&mut self) -> Option<i32> {
+        return match self.child.take() {
+            Some(mut child) => child.wait().ok().and_then(|status| status.code()),
+            None => self.exit_code.flatten(),
+        };
+    }
+}
+impl Iterator for Transaction {
+    type Item = XbpsEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Ok(line) = self.lines.recv() {
+            return Some(parse_event(line));
+        }
+        // Both streams are closed, so the process is done.
+        if self.child.is_none() && self.exit_code.is_none() {
+            return None;
+        }
+        let exit_code = self.wait();
+        self.exit_code = None;
+        return Some(XbpsEvent::Exited(exit_code));
+    }
+}
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        //! Don't leave a zombie behind, if the caller stopped reading early.
+        if let Some(mut child) = self.child.take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{xbps_cmd::*, xbps_events::XbpsErrorKind};
+
+    #[test]
+    fn test_scripted_transaction() {
+        let lines = vec![
+            OutputLine::Stdout("Name   Action    Version  New version  Download size".into()),
+            OutputLine::Stderr("ERROR: blender-4.0.2_1: broken, unresolvable shlib `libpython3.11.so.1.0'".into()),
+            OutputLine::Stderr("Transaction aborted due to unresolved shlibs.".into()),
+        ];
+        let events = Transaction::from_lines("xbps-install -Sy blender", lines, 8).collect::<Vec<XbpsEvent>>();
+        assert!(matches!(events[0], XbpsEvent::Message(_)));
+        assert!(matches!(&events[1], XbpsEvent::Error(err) if err.kind == XbpsErrorKind::BrokenShlib));
+        assert!(matches!(&events[2], XbpsEvent::Error(err) if err.kind == XbpsErrorKind::UnresolvedShlibs));
+        assert_eq!(events[3], XbpsEvent::Exited(Some(8)));
+        assert_eq!(events.len(), 4);
+    }
+    #[test]
+    fn test_spawn_pins_locale() {
+        // Any program works, the builder doesn't care that it isn't xbps.
+        let cmd = XbpsCommand::new("sh").args(&["-c", "echo $LC_ALL; echo oops >&2; exit 3"]);
+        let transaction = cmd.spawn().unwrap();
+        assert_eq!(transaction.command, "sh -c echo $LC_ALL; echo oops >&2; exit 3");
+        let mut events = transaction.collect::<Vec<XbpsEvent>>();
+        assert_eq!(events.pop(), Some(XbpsEvent::Exited(Some(3))));
+        // Each stream is read on its own thread, so their relative order isn't fixed.
+        events.sort_by_key(|x| x.to_string());
+        assert_eq!(events, vec![XbpsEvent::Message("C".into()), XbpsEvent::Message("oops".into())]);
+
+        let output = cmd.output().unwrap();
+        assert_eq!((output.stdout.as_str(), output.stderr.as_str(), output.status.code()), ("C\n", "oops\n", Some(3)));
+    }
+}
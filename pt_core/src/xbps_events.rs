@@ -0,0 +1,255 @@
+/*!
+ * Parses the output of xbps-install and xbps-remove (in the C locale) into XbpsEvents.
+ *
+ * Progress is reported per package, as <pkgver>: <state>
+ * blender-4.0.2_1.x86_64.xbps: 98MB [avg rate: 10MB/s]   -> Downloading
+ * blender-4.0.2_1: verifying RSA signature...             -> Verifying
+ * blender-4.0.2_1: unpacking ...                          -> Unpacking
+ * blender-4.0.2_1: configuring ...                        -> Configuring
+ * blender-4.0.2_1: installed successfully.                -> Installed
+ * blender-4.0.2_1: updated successfully.                  -> Updated
+ * blender-4.0.2_1: removed successfully.                  -> Removed
+ *
+ * Warnings are prefixed with "WARNING: ". Errors are either prefixed with "ERROR: ", or are one of
+ * the messages xbps prints to stderr before it gives up. Those are recognized, and tagged with the
+ * exit code xbps uses for them. Anything else is a Message, including those messages on stdout.
+ *
+ * Before giving up on unresolved shlibs, xbps names every package that is missing one:
+ * ERROR: blender-4.0.2_1: broken, unresolvable shlib `libpython3.11.so.1.0'
+ */
+use std::fmt::Display;
+
+/**
+ * A line of output, tagged with the stream it was written to.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+/**
+ * What happened during a transaction. Packages are identified by their pkgver (e.g. blender-4.0.2_1).
+ * Message: Anything that isn't recognized.
+ * Exited: Always the last event. Exit code is None if the process was killed by a signal.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum XbpsEvent {
+    Downloading(String),
+    Verifying(String),
+    Unpacking(String),
+    Configuring(String),
+    Installed(String),
+    Updated(String),
+    Removed(String),
+    Warning(String),
+    Error(XbpsError),
+    Message(String),
+    Exited(Option<i32>),
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct XbpsError {
+    pub kind: XbpsErrorKind,
+    pub message: String,
+}
+/**
+ * Reasons xbps gives up. Other covers every error that isn't recognized.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XbpsErrorKind {
+    NotFound,
+    InvalidDependencies,
+    UnresolvedDependencies,
+    UnresolvedShlibs,
+    Conflicts,
+    InsufficientSpace,
+    /// xbps itself must be updated before anything else can be.
+    XbpsUpdateRequired,
+    /// A package needs a shared library nothing provides. See XbpsError::broken_shlib().
+    BrokenShlib,
+    Other,
+}
+
+// Messages xbps prints for each kind of error.
+const ERROR_MESSAGES: [(&str, XbpsErrorKind); 8] = [
+    ("not found in repository pool", XbpsErrorKind::NotFound),
+    ("contains invalid dependencies", XbpsErrorKind::InvalidDependencies),
+    ("unresolved dependencies", XbpsErrorKind::UnresolvedDependencies),
+    ("unresolved shlibs", XbpsErrorKind::UnresolvedShlibs),
+    ("conflicting packages", XbpsErrorKind::Conflicts),
+    ("insufficient disk space", XbpsErrorKind::InsufficientSpace),
+    ("The 'xbps' package must be updated", XbpsErrorKind::XbpsUpdateRequired),
+    ("broken, unresolvable shlib", XbpsErrorKind::BrokenShlib),
+];
+const BROKEN_SHLIB: &str = ": broken, unresolvable shlib ";
+
+/// Builds the event for a package, from its pkgver.
+type PkgEvent = fn(String) -> XbpsEvent;
+
+// <pkgver>: <suffix> -> event
+const PROGRESS: [(&str, PkgEvent); 6] = [
+    ("verifying", XbpsEvent::Verifying),
+    ("unpacking", XbpsEvent::Unpacking),
+    ("configuring", XbpsEvent::Configuring),
+    ("installed successfully", XbpsEvent::Installed),
+    ("updated successfully", XbpsEvent::Updated),
+    ("removed successfully", XbpsEvent::Removed),
+];
+
+impl XbpsErrorKind {
+    pub fn code(&self) -> Option<i32> {
+        //! Exit code xbps uses for this error. These are errno values.
+        return match self {
+            XbpsErrorKind::NotFound => Some(2),
+            XbpsErrorKind::InvalidDependencies => Some(6),
+            XbpsErrorKind::UnresolvedShlibs => Some(8),
+            XbpsErrorKind::Conflicts => Some(11),
+            XbpsErrorKind::XbpsUpdateRequired => Some(16),
+            XbpsErrorKind::UnresolvedDependencies => Some(19),
+            XbpsErrorKind::InsufficientSpace => Some(28),
+            // Reported once per package, xbps exits with UnresolvedShlibs afterwards.
+            XbpsErrorKind::BrokenShlib | XbpsErrorKind::Other => None,
+        };
+    }
+    pub fn from_code(code: i32) -> XbpsErrorKind {
+        for (_, kind) in ERROR_MESSAGES.iter() {
+            if kind.code() == Some(code) {
+                return *kind;
+            }
+        }
+        return XbpsErrorKind::Other;
+    }
+    pub fn from_message(message: &str) -> XbpsErrorKind {
+        return ERROR_MESSAGES.iter()
+            .find(|(text, _)| message.contains(text))
+            .map(|(_, kind)| *kind)
+            .unwrap_or(XbpsErrorKind::Other);
+    }
+}
+
+impl XbpsError {
+    pub fn broken_shlib(&self) -> Option<(String, String)> {
+        //! (pkgver, shlib) of a BrokenShlib error, e.g. ("blender-4.0.2_1", "libpython3.11.so.1.0").
+        if self.kind != XbpsErrorKind::BrokenShlib {
+            return None;
+        }
+        let (pkgver, shlib) = self.message.split_once(BROKEN_SHLIB)?;
+        return Some((pkgver.trim().into(), shlib.trim().trim_matches(['`', '\'']).into()));
+    }
+}
+
+pub fn parse_event(line: OutputLine) -> XbpsEvent {
+    let (text, is_stderr) = match line {
+        OutputLine::Stdout(text) => (text, false),
+        OutputLine::Stderr(text) => (text, true),
+    };
+    if let Some(message) = text.strip_prefix("WARNING: ") {
+        return XbpsEvent::Warning(message.into());
+    }
+    let message = text.strip_prefix("ERROR: ").unwrap_or(&text);
+    let kind = XbpsErrorKind::from_message(message);
+    if text.starts_with("ERROR: ") || (is_stderr && kind != XbpsErrorKind::Other) {
+        return XbpsEvent::Error(XbpsError { kind, message: message.into() });
+    }
+    if let Some((pkgver, state)) = text.split_once(": ") {
+        if let Some(file) = pkgver.strip_suffix(".xbps") {
+            // <pkgver>.<arch>.xbps
+            let pkgver = file.rsplit_once('.').map(|(pkgver, _arch)| pkgver).unwrap_or(file);
+            return XbpsEvent::Downloading(pkgver.into());
+        }
+        if !pkgver.contains(char::is_whitespace) {
+            if let Some((_, event)) = PROGRESS.iter().find(|(prefix, _)| state.starts_with(prefix)) {
+                return event(pkgver.into());
+            }
+        }
+    }
+    return XbpsEvent::Message(text);
+}
+impl Display for XbpsEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            XbpsEvent::Downloading(pkgver) => write!(f, "{pkgver}: downloaded"),
+            XbpsEvent::Verifying(pkgver) => write!(f, "{pkgver}: verifying"),
+            XbpsEvent::Unpacking(pkgver) => write!(f, "{pkgver}: unpacking"),
+            XbpsEvent::Configuring(pkgver) => write!(f, "{pkgver}: configuring"),
+            XbpsEvent::Installed(pkgver) => write!(f, "{pkgver}: installed"),
+            XbpsEvent::Updated(pkgver) => write!(f, "{pkgver}: updated"),
+            XbpsEvent::Removed(pkgver) => write!(f, "{pkgver}: removed"),
+            XbpsEvent::Warning(message) | XbpsEvent::Message(message) => write!(f, "{message}"),
+            XbpsEvent::Error(err) => write!(f, "{err}"),
+            XbpsEvent::Exited(Some(code)) => write!(f, "Exited with status {code}"),
+            XbpsEvent::Exited(None) => write!(f, "Killed by a signal"),
+        };
+    }
+}
+impl Display for XbpsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self.kind.code() {
+            Some(code) => write!(f, "{} (code {code})", self.message),
+            None => write!(f, "{}", self.message),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xbps_events::*;
+
+    fn parse_stdout(text: &str) -> Vec<XbpsEvent> {
+        return text.lines().map(|x| parse_event(OutputLine::Stdout(x.into()))).collect();
+    }
+
+    #[test]
+    fn test_install_output() {
+        let output = "[*] Downloading packages
+blender-4.0.2_1.x86_64.xbps: 98MB [avg rate: 10MB/s]
+blender-4.0.2_1: verifying RSA signature...
+
+[*] Unpacking packages
+blender-4.0.2_1: unpacking ...
+python3-3.12.1_2: updating to 3.12.2_1 ...
+python3-3.12.2_1: unpacking ...
+blender-4.0.2_1: configuring ...
+blender-4.0.2_1: installed successfully.
+python3-3.12.2_1: updated successfully.
+WARNING: blender-4.0.2_1: failed to remove obsolete file
+";
+        let events = parse_stdout(output);
+        assert_eq!(events[1], XbpsEvent::Downloading("blender-4.0.2_1".into()));
+        assert_eq!(events[2], XbpsEvent::Verifying("blender-4.0.2_1".into()));
+        assert_eq!(events[5], XbpsEvent::Unpacking("blender-4.0.2_1".into()));
+        assert_eq!(events[6], XbpsEvent::Message("python3-3.12.1_2: updating to 3.12.2_1 ...".into()));
+        assert_eq!(events[8], XbpsEvent::Configuring("blender-4.0.2_1".into()));
+        assert_eq!(events[9], XbpsEvent::Installed("blender-4.0.2_1".into()));
+        assert_eq!(events[10], XbpsEvent::Updated("python3-3.12.2_1".into()));
+        assert_eq!(events[11], XbpsEvent::Warning("blender-4.0.2_1: failed to remove obsolete file".into()));
+    }
+    #[test]
+    fn test_errors() {
+        let stderr = |text: &str| parse_event(OutputLine::Stderr(text.into()));
+        assert_eq!(stderr("Package 'blendr' not found in repository pool."), XbpsEvent::Error(XbpsError { kind: XbpsErrorKind::NotFound, message: "Package 'blendr' not found in repository pool.".into() }));
+        assert!(matches!(stderr("The 'xbps' package must be updated, please run `xbps-install -u xbps`"), XbpsEvent::Error(err) if err.kind == XbpsErrorKind::XbpsUpdateRequired));
+
+        // The same messages on stdout are only errors if they are prefixed with ERROR:.
+        let events = parse_stdout("Package 'blendr' not found in repository pool.\nERROR: Package 'blendr' not found in repository pool.\nvim-9.0_1: removed successfully.");
+        assert_eq!(events[0], XbpsEvent::Message("Package 'blendr' not found in repository pool.".into()));
+        assert!(matches!(&events[1], XbpsEvent::Error(err) if err.kind == XbpsErrorKind::NotFound));
+        assert_eq!(events[2], XbpsEvent::Removed("vim-9.0_1".into()));
+
+        let err = parse_event(OutputLine::Stderr("Transaction aborted due to unresolved shlibs.".into()));
+        assert_eq!(err.to_string(), "Transaction aborted due to unresolved shlibs. (code 8)");
+        // Unrecognized errors are still errors, but other stderr output isn't.
+        let err = parse_event(OutputLine::Stderr("ERROR: [reposync] failed to fetch file".into()));
+        assert_eq!(err, XbpsEvent::Error(XbpsError { kind: XbpsErrorKind::Other, message: "[reposync] failed to fetch file".into() }));
+        assert_eq!(parse_event(OutputLine::Stderr("[*] Updating repository index".into())), XbpsEvent::Message("[*] Updating repository index".into()));
+
+        let err = match parse_event(OutputLine::Stderr("ERROR: blender-4.0.2_1: broken, unresolvable shlib `libpython3.11.so.1.0'".into())) {
+            XbpsEvent::Error(err) => err,
+            event => panic!("Expected an error, found {event:?}"),
+        };
+        assert_eq!(err.kind, XbpsErrorKind::BrokenShlib);
+        assert_eq!(err.broken_shlib(), Some(("blender-4.0.2_1".into(), "libpython3.11.so.1.0".into())));
+
+        assert_eq!(XbpsErrorKind::from_code(8), XbpsErrorKind::UnresolvedShlibs);
+        assert_eq!(XbpsErrorKind::from_code(1), XbpsErrorKind::Other);
+    }
+}
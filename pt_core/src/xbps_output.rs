@@ -0,0 +1,169 @@
+/*!
+ * Line-oriented parsers for the text xbps-query prints.
+ *
+ * xbps-query -Rs -> [*] <name>-<version>   <description>
+ * xbps-query -l  -> <state> <name>-<version>   <description>
+ *
+ * Output is read one line at a time, so the parsers can stream straight from a running process.
+ * Lines that can't be parsed are yielded as a ParseError, instead of becoming an empty package.
+ */
+use std::{fmt::Display, io::BufRead};
+
+use crate::{utils::split_name_block, QueryResult};
+
+/**
+ * A line that could not be parsed.
+ * line_num starts at 1. content is lossily decoded, if the line wasn't valid UTF-8.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line_num: usize,
+    pub content: String,
+    pub reason: String,
+}
+
+/**
+ * Iterator over the packages in xbps-query output.
+ * Blank lines are skipped.
+ */
+pub struct XbpsLines<R: BufRead> {
+    reader: R,
+    line_num: usize,
+    parse_line: fn(&str) -> Result<QueryResult, String>,
+}
+
+impl<R: BufRead> XbpsLines<R> {
+    pub fn search_output(reader: R) -> XbpsLines<R> {
+        //! Output of xbps-query -Rs
+        return XbpsLines { reader, line_num: 0, parse_line: parse_search_line };
+    }
+    pub fn list_output(reader: R) -> XbpsLines<R> {
+        //! Output of xbps-query -l
+        return XbpsLines { reader, line_num: 0, parse_line: parse_list_line };
+    }
+    pub fn collect_valid(self) -> (Vec<QueryResult>, Vec<ParseError>) {
+        //! Split output into the packages, and the lines that couldn't be parsed.
+        let mut pkgs: Vec<QueryResult> = Vec::new();
+        let mut errors: Vec<ParseError> = Vec::new();
+        for res in self {
+            match res {
+                Ok(pkg) => pkgs.push(pkg),
+                Err(err) => errors.push(err),
+            }
+        }
+        return (pkgs, errors);
+    }
+}
+impl<R: BufRead> Iterator for XbpsLines<R> {
+    type Item = Result<QueryResult, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf: Vec<u8> = Vec::new();
+        loop {
+            buf.clear();
+            self.line_num += 1;
+            let line_num = self.line_num;
+            let error = |content: String, reason: String| ParseError { line_num, content, reason };
+            match self.reader.read_until(b'\n', &mut buf) {
+                Ok(0) => return None,
+                Ok(_) => (),
+                Err(msg) => return Some(Err(error("".into(), msg.to_string()))),
+            };
+            let line = match std::str::from_utf8(&buf) {
+                Ok(line) => line.trim_end(),
+                Err(msg) => return Some(Err(error(String::from_utf8_lossy(&buf).trim_end().to_string(), msg.to_string()))),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some((self.parse_line)(line).map_err(|reason| error(line.to_string(), reason)));
+        }
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "Line {}: {} ('{}')", self.line_num, self.reason, self.content);
+    }
+}
+
+fn parse_search_line(line: &str) -> Result<QueryResult, String> {
+    //! [*] blender-4.0.2_1   3D graphics creation suite
+    let (is_installed, rest) = if let Some(rest) = line.strip_prefix("[*]") {
+        (true, rest)
+    } else if let Some(rest) = line.strip_prefix("[-]") {
+        (false, rest)
+    } else {
+        return Err("Expected '[*]' or '[-]'".into());
+    };
+    return Ok(QueryResult { is_installed, ..parse_name_and_description(rest)? });
+}
+fn parse_list_line(line: &str) -> Result<QueryResult, String> {
+    //! ii blender-4.0.2_1   3D graphics creation suite
+    let rest = match line.split_once(char::is_whitespace) {
+        Some((_state, rest)) => rest,
+        None => return Err("Expected a package state".into()),
+    };
+    return Ok(QueryResult { is_installed: true, ..parse_name_and_description(rest)? });
+}
+fn parse_name_and_description(rest: &str) -> Result<QueryResult, String> {
+    let rest = rest.trim_start();
+    let (name_block, description) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if name_block.is_empty() {
+        return Err("Missing package name".into());
+    }
+    let (pkg_name, pkg_version) = split_name_block(name_block);
+    if pkg_name.is_empty() || pkg_version.is_empty() {
+        return Err(format!("Expected <name>-<version>, found '{name_block}'"));
+    }
+    return Ok(QueryResult {
+        pkg_name,
+        pkg_version,
+        pkg_description: description.trim().to_string(),
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xbps_output::*;
+
+    const XRS: &[u8] = include_bytes!("../fixtures/xbps-query-Rs.txt");
+    const XQL: &[u8] = include_bytes!("../fixtures/xbps-query-l.txt");
+
+    #[test]
+    fn test_search_output() {
+        let (pkgs, errors) = XbpsLines::search_output(XRS).collect_valid();
+        assert_eq!(errors, vec![]);
+        assert_eq!(pkgs.len(), 8);
+        assert_eq!(pkgs[0].pkg_name, "0ad");
+        assert_eq!(pkgs[0].pkg_version, "0.0.26_1");
+        assert!(pkgs[1].is_installed);
+        assert_eq!(pkgs[1].pkg_name, "bash");
+        assert_eq!(pkgs[1].pkg_description, "GNU Bourne Again Shell");
+        // Multi-byte characters survive.
+        assert_eq!(pkgs[3].pkg_name, "fortune-mod-de");
+        assert_eq!(pkgs[3].pkg_description, "Collection of German fortune cookies – „Glückskekse“");
+        assert_eq!(pkgs[4].pkg_description, "Google’s Japanese input method editor – 日本語入力");
+        // Name longer than the description column.
+        assert_eq!(pkgs[7].pkg_name, "python3-sphinxcontrib-applehelp");
+    }
+    #[test]
+    fn test_list_output() {
+        let (pkgs, errors) = XbpsLines::list_output(XQL).collect_valid();
+        assert_eq!(errors, vec![]);
+        assert_eq!(pkgs.iter().map(|p| p.pkg_name.as_str()).collect::<Vec<&str>>(), vec!["base-system", "bash", "zsh"]);
+        assert!(pkgs.iter().all(|p| p.is_installed));
+        assert_eq!(pkgs[2].pkg_description, "Z shell – extended Bourne shell");
+    }
+    #[test]
+    fn test_malformed_lines() {
+        let output: &[u8] = b"[-] blender-4.0.2_1   3D graphics creation suite\n\n[?] vim-9.0_1   Vi IMproved\n[-] nameonly\n[*] caf\xe9-1.0_1   Latin-1\n";
+        let res = XbpsLines::search_output(output).collect::<Vec<_>>();
+        assert_eq!(res.len(), 4);
+        assert_eq!(res[0].as_ref().unwrap().pkg_name, "blender");
+        // Blank line 2 is skipped, but still counted.
+        assert_eq!(res[1].as_ref().unwrap_err().line_num, 3);
+        assert_eq!(res[2].as_ref().unwrap_err().reason, "Expected <name>-<version>, found 'nameonly'");
+        assert_eq!(res[3].as_ref().unwrap_err().content, "[*] caf\u{fffd}-1.0_1   Latin-1");
+    }
+}
@@ -1,14 +1,13 @@
-use std::io::{BufRead, BufReader};
+use std::process::ExitCode;
 
-use duct::cmd;
-use mythos_core::{cli::{clean_cli_args, get_user_permission}, logger, printerror};
-use pt_core::{validate_pkgs, Query};
+use mythos_core::{cli::{clean_cli_args, get_user_permission}, logger, printerror, printinfo, printwarn};
+use pt_core::{backend::{PackageBackend, XbpsBackend}, charon, tertiary::{run_tertiary, ExternalManager, TertiaryAction}, validate_pkgs_tertiary, xbps_events::{XbpsErrorKind, XbpsEvent}, PkgOrigin, Query, QueryResult, SearchOpts};
 enum StartState {
     Install,
     SysUpdate,
     XbpsUpdate,
 }
-fn main() {
+fn main() -> ExitCode {
     let _ = logger::set_id("STYX");
     // let args = std::env::args().skip(1);
     let args = clean_cli_args();
@@ -16,14 +15,16 @@ fn main() {
     let mut starting_state = StartState::Install;
     let mut do_dry_run = false;
     let mut assume_yes = false;
+    let mut backend = XbpsBackend::new(ExternalManager::load());
+    let mut search_opts = SearchOpts::load();
 
     for arg in args {
         if arg == "-h" || arg == "--help" {
             println!("Wrapper util for xbps-install");
             println!("styx [opts] packages");
             println!("opts:");
-            println!("-h | --help\t\tPrint this menu.\n-u | --update\t\tRun a system update. Equiv to xbps-install -Syu.\n-x | --xbps-update\t\tUpdate xbps. Contains an implicit '-u'.\n-n | --dryrun\t\tRun command w/o making changes to system.\n-y | --assume-yes\t\tAssume yes to all questions.");
-            return;
+            println!("-h | --help\t\tPrint this menu.\n-u | --update\t\tRun a system update. Equiv to xbps-install -Syu. Also offers to rebuild outdated charon programs.\n-x | --xbps-update\t\tUpdate xbps. Contains an implicit '-u'.\n-n | --dryrun\t\tRun command w/o making changes to system.\n-y | --assume-yes\t\tAssume yes to all questions.\n-d | --descriptions\t\tAlso search package descriptions.\n-e | --exact\t\tOnly accept exact package names. Exit with an error if any are missing.\n--glob | --regex\t\tTreat pkgs as glob or regex patterns. Detected by syntax otherwise (font-*, ^python3-.*-devel$).\n--scorer=<fuzzy|prefix|substring|levenshtein|regex>\t\tStrategy used to match packages.\n--threshold=<0-1>\t\tMinimum score a package needs to match.\n--refresh-index\t\tRebuild the package search index.\nPackages can be pinned to versions with xbps patterns: foo>=1.2, foo<2.0, foo-1.2_1");
+            return ExitCode::SUCCESS;
         } 
         if arg == "-u" || arg == "--update" {
             starting_state = StartState::SysUpdate;
@@ -33,6 +34,10 @@ fn main() {
             do_dry_run = true;
         } else if arg == "-y" || arg == "--assume-yes" {
             assume_yes = true;
+        } else if arg == "--refresh-index" {
+            backend.refresh_index = true;
+        } else if search_opts.parse_arg(&arg) {
+            continue;
         } else if arg.starts_with("-") {
             printerror!("Unknown arg: {arg}");
         } else {
@@ -40,120 +45,204 @@ fn main() {
         }
     }
 
-    let _ = match starting_state {
-        StartState::Install => install_pkgs(pkgs, do_dry_run, assume_yes),
-        StartState::SysUpdate => sys_update(assume_yes, do_dry_run),
+    let res = match starting_state {
+        StartState::Install => install_pkgs(&backend, pkgs, &search_opts, do_dry_run, assume_yes),
+        StartState::SysUpdate => sys_update(&backend, assume_yes, do_dry_run)
+            .and_then(|_| update_charon_pkgs(assume_yes, do_dry_run)),
         StartState::XbpsUpdate => {
-            match xbps_update(assume_yes, do_dry_run) {
-                Ok(_) => sys_update(assume_yes, do_dry_run),
+            match xbps_update(&backend, assume_yes, do_dry_run) {
+                Ok(_) => sys_update(&backend, assume_yes, do_dry_run)
+                    .and_then(|_| update_charon_pkgs(assume_yes, do_dry_run)),
                 Err(err) => Err(err),
             }
         },
     };
+    if let Err(msg) = res {
+        printerror!("{msg}");
+        return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
 }
 
-fn install_pkgs(pkgs: Vec<String>, do_dry_run: bool, assume_yes: bool) -> Result<(), std::io::Error> { 
+fn install_pkgs(backend: &dyn PackageBackend, pkgs: Vec<String>, search_opts: &SearchOpts, do_dry_run: bool, assume_yes: bool) -> Result<(), std::io::Error> { 
     //! Validate and install packages.
-    let query = Query::from(match validate_pkgs(pkgs.into_iter()) {
-        Some(pkgs) => pkgs,
-        None => {
+    let (query, tertiary) = match validate_pkgs_tertiary(backend, pkgs.into_iter(), search_opts) {
+        Ok(Some((pkgs, tertiary))) => (Query::from(pkgs), tertiary),
+        Ok(None) => {
             println!("All packages removed. Exiting...");
             return Ok(());
         },
-    });
+        Err(errors) => return Err(std::io::Error::other(errors.join("\n"))),
+    };
+    // Only names xbps and charon don't have end up here, so they never overlap the install below.
+    run_tertiary(&tertiary, TertiaryAction::Install, do_dry_run, assume_yes);
+    if query.len() == 0 {
+        return Ok(());
+    }
 
-    let pkg_names = query.get_pkg_names();
+    // xbps-install doesn't know about charon programs, it would report them as not found.
+    let (charon_pkgs, xbps_pkgs): (Vec<QueryResult>, Vec<QueryResult>) = query.into_iter().partition(|p| p.origin == PkgOrigin::Charon);
+
+    // xbps-install accepts package patterns, so version constraints are passed along.
+    let pkg_names = xbps_pkgs.iter().map(|p| match &p.pkg_spec {
+        Some(spec) => spec.to_string(),
+        None => p.pkg_name.to_owned(),
+    }).collect::<Vec<String>>();
+    let pkg_names = pkg_names.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
 
     // Double check before installing, unless user used -y.
     if !assume_yes {
-        let msg =  "The following packages will be installed:\n".to_owned() 
-            + &pkg_names.join("\n")
-            + "\n\nWould you like to continue? ";
+        let mut msg = "The following packages will be installed:\n".to_owned();
+        if xbps_pkgs.len() > 0 {
+            let plan = backend.plan_install(&pkg_names)?;
+            plan.report();
+            msg += &format!("{}\n", plan.table());
+        }
+        if charon_pkgs.len() > 0 {
+            msg += &format!("\nRebuilt by charon:\n{}\n", charon_pkgs.iter().map(|p| p.list_name()).collect::<Vec<String>>().join("\n"));
+        }
+        msg += "\nWould you like to continue? ";
         if !get_user_permission(assume_yes,  &msg) {
             return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Cancelling installation..."));
         }
     }
 
-    // Build args for command.
-    let mut args = vec!["-Sy"];
-    if do_dry_run {
-        args.push("-n".into());
+    // xbps goes first, charon programs may need its packages to build.
+    if xbps_pkgs.len() > 0 {
+        install_xbps_pkgs(backend, &pkg_names, do_dry_run, assume_yes)?;
     }
-    args.extend(pkg_names);
-
-    // Build command.
-    let cmd = cmd("xbps-install", args)
-        .stderr_to_stdout()
-        .unchecked();
-
+    for pkg in charon_pkgs {
+        if let Err(msg) = charon::install(&pkg.pkg_name, do_dry_run) {
+            printerror!("{msg}");
+        }
+    }
+    return Ok(());
+}
+fn install_xbps_pkgs(backend: &dyn PackageBackend, pkg_names: &[&str], do_dry_run: bool, assume_yes: bool) -> Result<(), std::io::Error> {
     // If an update is required, run the install command again.
-    loop {
-        let mut lines = BufReader::new(cmd.reader()?).lines();
-        // Loop through lines in bufreader.
-        loop {
-            let line = match lines.next() {
-                Some(line) => line?,
-                None => return Ok(())
+    // Broken shlibs get a targeted update first, and a full system update if that didn't fix them.
+    // Each is only tried once, so a dry run (which changes nothing) doesn't retry forever.
+    let (mut tried_targeted, mut tried_full, mut tried_xbps) = (false, false, false);
+    'install: loop {
+        let mut broken: Vec<(String, String)> = Vec::new();
+        let transaction = backend.install(pkg_names, do_dry_run)?;
+        let command = transaction.command.to_owned();
+        for event in transaction {
+            print_event(&event, &command)?;
+            let err = match event {
+                XbpsEvent::Error(err) => err,
+                _ => continue,
             };
-
-            println!("{line}");
-
-            if line.contains("shlibs") 
-                && get_user_permission(assume_yes, "System needs to be updated.") {
-                    sys_update(assume_yes, do_dry_run)?;
-                    break;
-            } 
+            if let Some(shlib) = err.broken_shlib() {
+                broken.push(shlib);
+            }
+            if err.kind == XbpsErrorKind::UnresolvedShlibs && !tried_targeted {
+                tried_targeted = true;
+                if update_pkgs(backend, &backend.shlib_updates(&broken)?, assume_yes, do_dry_run)? {
+                    continue 'install;
+                }
+            }
+            if err.kind == XbpsErrorKind::UnresolvedShlibs && !tried_full && get_user_permission(assume_yes, "System needs to be updated.") {
+                tried_full = true;
+                sys_update(backend, assume_yes, do_dry_run)?;
+                continue 'install;
+            }
             // This is here just in case.
-            if line.contains("The 'xbps' package must be updated") {
-                xbps_update(assume_yes, do_dry_run)?;
-                break;
+            if err.kind == XbpsErrorKind::XbpsUpdateRequired && !tried_xbps {
+                tried_xbps = true;
+                xbps_update(backend, assume_yes, do_dry_run)?;
+                continue 'install;
             }
         }
-        
+        return Ok(());
+    }
+}
+fn update_pkgs(backend: &dyn PackageBackend, pkg_names: &[String], assume_yes: bool, do_dry_run: bool) -> Result<bool, std::io::Error> {
+    //! Update only pkg_names. Returns false if there is nothing to update, the user declined, or the update failed.
+    if pkg_names.len() == 0 {
+        return Ok(false);
+    }
+    let msg = format!("Updating {} should fix the broken shlibs. Would you like to continue? ", pkg_names.join(", "));
+    if !get_user_permission(assume_yes, &msg) {
+        return Ok(false);
+    }
+    let mut is_success = false;
+    let transaction = backend.update(&pkg_names.iter().map(|x| x.as_str()).collect::<Vec<&str>>(), do_dry_run)?;
+    let command = transaction.command.to_owned();
+    for event in transaction {
+        // A failed targeted update isn't fatal, the full system update is tried next.
+        if let Err(msg) = print_event(&event, &command) {
+            printerror!("{msg}");
+        }
+        if event == XbpsEvent::Exited(Some(0)) {
+            is_success = true;
+        }
     }
+    return Ok(is_success);
 }
-fn sys_update(assume_yes: bool, do_dry_run: bool) -> Result<(), std::io::Error>{
+fn sys_update(backend: &dyn PackageBackend, assume_yes: bool, do_dry_run: bool) -> Result<(), std::io::Error>{
     if !assume_yes {
         if !get_user_permission(assume_yes,  "Running a system update. Would you like to continue?") {
             return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Cancelling update..."));
         }
     }
-    let mut args = vec!["-Syu"];
-    if do_dry_run {
-        args.push("-n");
-    }
-    let cmd = cmd("xbps-install", args)
-        .stderr_to_stdout()
-        .unchecked();
-    loop {
-        // If xbps update is needed, rerun this command.
-        let mut lines = BufReader::new(cmd.reader()?).lines();
-        // Iterate over lines in output.
-        loop {
-            let line = match lines.next() {
-                Some(line) => line?,
-                None => return Ok(())
-            };
-            println!("{line}");
-            if line.contains("The 'xbps' package must be updated") {
-                xbps_update(assume_yes, do_dry_run)?;
-                break;
+    // If xbps update is needed, rerun this command. Only once, a dry run doesn't actually update xbps.
+    let mut tried_xbps = false;
+    'update: loop {
+        let transaction = backend.update(&[], do_dry_run)?;
+        let command = transaction.command.to_owned();
+        for event in transaction {
+            print_event(&event, &command)?;
+            if !tried_xbps && matches!(&event, XbpsEvent::Error(err) if err.kind == XbpsErrorKind::XbpsUpdateRequired) {
+                tried_xbps = true;
+                xbps_update(backend, assume_yes, do_dry_run)?;
+                continue 'update;
             }
         }
+        return Ok(());
     }
 }
-fn xbps_update(assume_yes: bool, do_dry_run: bool)-> Result<(), std::io::Error> {
+fn update_charon_pkgs(assume_yes: bool, do_dry_run: bool) -> Result<(), std::io::Error> {
+    //! Offer to rebuild charon programs whose source tree has a newer version than the one installed.
+    let outdated = charon::list_outdated();
+    if outdated.len() == 0 {
+        return Ok(());
+    }
+    let msg = "The following charon programs are out of date:\n".to_owned()
+        + &outdated.iter().map(|p| format!("{} ({} -> {})", p.pkg_name, p.installed_version, p.source_version)).collect::<Vec<String>>().join("\n")
+        + "\n\nWould you like to rebuild them? ";
+    if !get_user_permission(assume_yes, &msg) {
+        return Ok(());
+    }
+    for pkg in outdated {
+        if let Err(msg) = charon::install(&pkg.pkg_name, do_dry_run) {
+            printerror!("{msg}");
+        }
+    }
+    return Ok(());
+}
+fn xbps_update(backend: &dyn PackageBackend, assume_yes: bool, do_dry_run: bool)-> Result<(), std::io::Error> {
     if !get_user_permission(assume_yes,  "xbps package needs to be updated. Would you like to continue?") {
         return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Cancelling xbps update..."));
     }
-    let mut args = vec!["-Syu"];
-    if do_dry_run {
-        args.push("-n");
+    let transaction = backend.update(&["xbps"], do_dry_run)?;
+    let command = transaction.command.to_owned();
+    for event in transaction {
+        print_event(&event, &command)?;
     }
-    args.push("xbps");
-
-    cmd("xbps-install", args).unchecked().run()?;
-
+    return Ok(());
+}
+fn print_event(event: &XbpsEvent, command: &str) -> Result<(), std::io::Error> {
+    //! Show output of command as it arrives. Finished packages are highlighted. Err if command exited with an error.
+    match event {
+        XbpsEvent::Installed(pkgver) => printinfo!("Installed {pkgver}"),
+        XbpsEvent::Updated(pkgver) => printinfo!("Updated {pkgver}"),
+        XbpsEvent::Warning(msg) => printwarn!("{msg}"),
+        XbpsEvent::Error(err) => printerror!("{err}"),
+        XbpsEvent::Exited(Some(0)) => (),
+        XbpsEvent::Exited(_) => return Err(std::io::Error::other(format!("'{command}' failed. {event}"))),
+        _ => println!("{event}"),
+    };
     return Ok(());
 }
 
@@ -168,5 +257,70 @@ mod test {
         * - Try update, but xbps is also ood.
         * - Try update xbps.
      */
+    use pt_core::backend::{fake_pkg, FakeBackend};
+    use crate::*;
 
+    #[test]
+    fn test_install_exact_pkg() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), fake_pkg("vim", false)]);
+        install_pkgs(&backend, vec!["blender".into()], &SearchOpts::default(), false, true).unwrap();
+        assert_eq!(backend.calls(), vec!["install blender"]);
+        assert_eq!(backend.list_installed().unwrap()[0].pkg_name, "blender");
+    }
+    #[test]
+    fn test_exact_missing_pkg_is_err() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false)]);
+        let search_opts = SearchOpts { exact: true, ..Default::default() };
+        assert!(install_pkgs(&backend, vec!["blender".into(), "blendr".into()], &search_opts, false, true).is_err());
+        assert_eq!(backend.calls(), Vec::<String>::new());
+    }
+    #[test]
+    fn test_charon_pkgs_skip_xbps() {
+        let charon_pkg = QueryResult { origin: PkgOrigin::Charon, ..fake_pkg("plutonian-shores", true) };
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), charon_pkg]);
+        install_pkgs(&backend, vec!["blender".into(), "plutonian-shores".into()], &SearchOpts::default(), true, true).unwrap();
+        assert_eq!(backend.calls(), vec!["install blender"]);
+    }
+    #[test]
+    fn test_install_pinned_pkg() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false), fake_pkg("vim", false)]);
+        install_pkgs(&backend, vec!["blender>=1.0<2.0".into(), "vim".into()], &SearchOpts::default(), true, true).unwrap();
+        assert_eq!(backend.calls(), vec!["install blender>=1.0<2.0 vim"]);
+    }
+    #[test]
+    fn test_unsatisfied_pin_is_err() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false)]);
+        assert!(install_pkgs(&backend, vec!["blender>=2.0".into()], &SearchOpts::default(), true, true).is_err());
+        assert_eq!(backend.calls(), Vec::<String>::new());
+    }
+    #[test]
+    fn test_failed_install_is_err() {
+        let mut backend = FakeBackend::new(vec![fake_pkg("blender", false)]);
+        backend.exit_code = 1;
+        let err = install_pkgs(&backend, vec!["blender".into()], &SearchOpts::default(), false, true).unwrap_err();
+        assert_eq!(err.to_string(), "'fake install' failed. Exited with status 1");
+    }
+    #[test]
+    fn test_targeted_shlib_update() {
+        let python_old = fake_pkg("python3", true);
+        let python_new = QueryResult { pkg_version: "3.12.2_1".into(), pkg_shlib_provides: vec!["libpython3.12.so.1.0".into()], ..fake_pkg("python3", false) };
+        let mut backend = FakeBackend::new(vec![fake_pkg("blender", false), python_old, python_new, fake_pkg("vim", true)]);
+        backend.stderr = "ERROR: blender-1.0_1: broken, unresolvable shlib `libpython3.12.so.1.0'\nTransaction aborted due to unresolved shlibs.".into();
+        install_pkgs(&backend, vec!["blender".into()], &SearchOpts::default(), true, true).unwrap();
+        // FakeBackend always fails the same way, so the full update is still tried once afterwards.
+        assert_eq!(backend.calls(), vec!["install blender", "update python3", "install blender", "update", "install blender"]);
+    }
+    #[test]
+    fn test_xbps_update_is_tried_once() {
+        let mut backend = FakeBackend::new(vec![fake_pkg("blender", false)]);
+        backend.stderr = "The 'xbps' package must be updated, please run `xbps-install -u xbps`".into();
+        install_pkgs(&backend, vec!["blender".into()], &SearchOpts::default(), true, true).unwrap();
+        assert_eq!(backend.calls(), vec!["install blender", "update xbps", "install blender"]);
+    }
+    #[test]
+    fn test_update() {
+        let backend = FakeBackend::new(vec![fake_pkg("blender", false)]);
+        sys_update(&backend, true, false).unwrap();
+        assert_eq!(backend.calls(), vec!["update"]);
+    }
 }